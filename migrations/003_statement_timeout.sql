@@ -0,0 +1,14 @@
+-- ============================================================================
+-- PER-DATABASE STATEMENT TIMEOUT
+-- ============================================================================
+--
+-- Maximum execution time of a query, in milliseconds. Works as a ceiling:
+-- clients can ask for a shorter timeout with the X-Postgate-Timeout header,
+-- never a longer one. NULL uses the server default (30 seconds).
+--
+-- Enforced both by postgate and inside Postgres (SET LOCAL statement_timeout).
+--
+
+ALTER TABLE postgate_databases
+    ADD COLUMN statement_timeout_ms integer
+    CONSTRAINT positive_statement_timeout CHECK (statement_timeout_ms > 0);
@@ -0,0 +1,76 @@
+-- ============================================================================
+-- TOKEN EXPIRATION
+-- ============================================================================
+--
+-- Optional expiry date of a token. Expired tokens are rejected with the
+-- TOKEN_EXPIRED error code, NULL never expires.
+--
+-- Expired rows are kept (they still show up when listing tokens) until they
+-- are deleted explicitly.
+--
+
+ALTER TABLE postgate_tokens
+    ADD COLUMN expires_at timestamp with time zone;
+
+-- ----------------------------------------------------------------------------
+-- create_tenant_token(database_id, name, permissions, expires_at)
+-- ----------------------------------------------------------------------------
+-- Same as the 001 version, with an optional expiry date.
+--
+-- Parameters:
+--   p_expires_at: Expiry date of the token (default: NULL, never expires)
+--
+-- Example (token valid for 7 days):
+--   SELECT * FROM create_tenant_token(
+--       'abc-123...'::uuid,
+--       'preview',
+--       ARRAY['SELECT'],
+--       NOW() + INTERVAL '7 days'
+--   );
+--
+
+-- Adding a parameter creates an overload, drop the previous signature first
+DROP FUNCTION IF EXISTS create_tenant_token(uuid, character varying, text[]);
+
+CREATE OR REPLACE FUNCTION create_tenant_token(
+    p_database_id uuid,
+    p_name character varying(100) DEFAULT 'default',
+    p_permissions text[] DEFAULT ARRAY['SELECT', 'INSERT', 'UPDATE', 'DELETE'],
+    p_expires_at timestamp with time zone DEFAULT NULL
+) RETURNS TABLE (
+    id uuid,
+    token text
+) AS $$
+DECLARE
+    v_id uuid;
+    v_token_bytes bytea;
+    v_token_hex text;
+    v_full_token text;
+    v_token_hash text;
+    v_token_prefix text;
+BEGIN
+    -- Verify database exists
+    IF NOT EXISTS (SELECT 1 FROM postgate_databases WHERE postgate_databases.id = p_database_id) THEN
+        RAISE EXCEPTION 'Database not found: %', p_database_id;
+    END IF;
+
+    -- Generate 32 cryptographically secure random bytes
+    v_token_bytes := gen_random_bytes(32);
+    v_token_hex := encode(v_token_bytes, 'hex');
+
+    -- Build token: pg_ prefix + 64 hex chars = 67 chars total
+    v_token_prefix := 'pg_' || substring(v_token_hex from 1 for 5);
+    v_full_token := 'pg_' || v_token_hex;
+
+    -- Hash with SHA-256 (this is what gets stored)
+    v_token_hash := encode(digest(v_full_token, 'sha256'), 'hex');
+
+    -- Insert token record (only hash is stored)
+    INSERT INTO postgate_tokens (database_id, name, token_hash, token_prefix, allowed_operations, expires_at)
+    VALUES (p_database_id, p_name, v_token_hash, v_token_prefix, p_permissions, p_expires_at)
+    RETURNING postgate_tokens.id INTO v_id;
+
+    -- Return the full token - THIS IS THE ONLY TIME IT'S AVAILABLE!
+    RETURN QUERY SELECT v_id, v_full_token;
+END;
+$$ LANGUAGE plpgsql;
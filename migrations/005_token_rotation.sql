@@ -0,0 +1,70 @@
+-- ============================================================================
+-- TOKEN ROTATION
+-- ============================================================================
+--
+-- Rotating a token issues a new secret for the same row (id, name and
+-- permissions are kept). The previous hash stays valid until
+-- previous_token_expires_at, so workers still holding the old secret keep
+-- working while the new one is rolled out.
+--
+-- Only the last previous secret is kept: rotating again during the grace
+-- period invalidates the oldest one immediately.
+--
+
+ALTER TABLE postgate_tokens
+    ADD COLUMN previous_token_hash character varying(64),
+    ADD COLUMN previous_token_expires_at timestamp with time zone;
+
+-- Old secrets are looked up like current ones
+CREATE INDEX idx_postgate_tokens_previous_hash ON postgate_tokens(previous_token_hash)
+    WHERE previous_token_hash IS NOT NULL;
+
+-- ----------------------------------------------------------------------------
+-- rotate_tenant_token(token_id, grace_interval)
+-- ----------------------------------------------------------------------------
+-- Issues a new secret for an existing token.
+--
+-- IMPORTANT: The full token is returned ONLY at rotation time!
+--
+-- Parameters:
+--   p_token_id: UUID of the token to rotate
+--   p_grace_interval: How long the previous secret stays valid (default: 1 hour)
+--
+-- Returns:
+--   id: UUID of the token (unchanged)
+--   token: New full token string (pg_xxx...) - SAVE THIS!
+--
+-- Example:
+--   SELECT * FROM rotate_tenant_token('xyz-789...'::uuid, INTERVAL '1 day');
+--
+
+CREATE OR REPLACE FUNCTION rotate_tenant_token(
+    p_token_id uuid,
+    p_grace_interval interval DEFAULT INTERVAL '1 hour'
+) RETURNS TABLE (
+    id uuid,
+    token text
+) AS $$
+DECLARE
+    v_token_hex text;
+    v_full_token text;
+BEGIN
+    -- Same format as create_tenant_token
+    v_token_hex := encode(gen_random_bytes(32), 'hex');
+    v_full_token := 'pg_' || v_token_hex;
+
+    UPDATE postgate_tokens
+    SET previous_token_hash = postgate_tokens.token_hash,
+        previous_token_expires_at = NOW() + p_grace_interval,
+        token_hash = encode(digest(v_full_token, 'sha256'), 'hex'),
+        token_prefix = 'pg_' || substring(v_token_hex from 1 for 5)
+    WHERE postgate_tokens.id = p_token_id;
+
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Token not found: %', p_token_id;
+    END IF;
+
+    -- Return the full token - THIS IS THE ONLY TIME IT'S AVAILABLE!
+    RETURN QUERY SELECT p_token_id, v_full_token;
+END;
+$$ LANGUAGE plpgsql;
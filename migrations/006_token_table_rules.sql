@@ -0,0 +1,17 @@
+-- ============================================================================
+-- PER-TOKEN TABLE RULES
+-- ============================================================================
+--
+-- Restrict the tables a token can access:
+--   allowed_tables: only these tables can be referenced (NULL: every table)
+--   denied_tables: these tables can never be referenced (wins over allowed_tables)
+--
+-- Table names are compared case-insensitively by the SQL validator.
+--
+-- Example (token that can only touch the events table):
+--   UPDATE postgate_tokens SET allowed_tables = ARRAY['events'] WHERE id = 'xyz-789...';
+--
+
+ALTER TABLE postgate_tokens
+    ADD COLUMN allowed_tables text[],
+    ADD COLUMN denied_tables text[] NOT NULL DEFAULT '{}';
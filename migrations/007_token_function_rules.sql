@@ -0,0 +1,19 @@
+-- ============================================================================
+-- PER-TOKEN FUNCTION RULES
+-- ============================================================================
+--
+-- The SQL validator denies dangerous functions by default (pg_sleep,
+-- set_config, pg_read_file, lo_import, dblink, pg_terminate_backend, ...).
+-- Tokens can adjust that list:
+--   allowed_functions: functions allowed even if denied by default
+--   denied_functions: extra functions that can never be called (wins over allowed_functions)
+--
+-- Function names are compared case-insensitively, a trailing '*' matches a prefix.
+--
+-- Example (token that may call pg_sleep):
+--   UPDATE postgate_tokens SET allowed_functions = ARRAY['pg_sleep'] WHERE id = 'xyz-789...';
+--
+
+ALTER TABLE postgate_tokens
+    ADD COLUMN allowed_functions text[] NOT NULL DEFAULT '{}',
+    ADD COLUMN denied_functions text[] NOT NULL DEFAULT '{}';
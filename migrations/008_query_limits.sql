@@ -0,0 +1,23 @@
+-- ============================================================================
+-- PER-DATABASE QUERY COMPLEXITY LIMITS
+-- ============================================================================
+--
+-- Checked by the SQL validator before a query reaches Postgres. NULL means
+-- no limit.
+--   max_joins: joins in the whole statement (comma-separated FROM items count)
+--   max_subquery_depth: nesting depth of subqueries and CTEs
+--   max_in_list: values in a single IN (...) list
+--   max_params: distinct $n parameters
+--   max_sql_length: length of the SQL text, in bytes
+--
+-- Example (keep a tenant away from large cross joins):
+--   UPDATE postgate_databases SET max_joins = 4 WHERE id = 'xyz-789...';
+--
+
+ALTER TABLE postgate_databases
+    ADD COLUMN max_joins integer CONSTRAINT non_negative_max_joins CHECK (max_joins >= 0),
+    ADD COLUMN max_subquery_depth integer
+        CONSTRAINT non_negative_max_subquery_depth CHECK (max_subquery_depth >= 0),
+    ADD COLUMN max_in_list integer CONSTRAINT positive_max_in_list CHECK (max_in_list > 0),
+    ADD COLUMN max_params integer CONSTRAINT non_negative_max_params CHECK (max_params >= 0),
+    ADD COLUMN max_sql_length integer CONSTRAINT positive_max_sql_length CHECK (max_sql_length > 0);
@@ -0,0 +1,14 @@
+-- ============================================================================
+-- SAFE UPDATES
+-- ============================================================================
+--
+-- Like MySQL's --safe-updates: tokens with safe_updates can't run UPDATE or
+-- DELETE without a WHERE clause, or with a WHERE clause that doesn't filter
+-- rows (1=1, true, id = id, ... OR 1=1). Data-modifying CTEs are checked too.
+--
+-- Example:
+--   UPDATE postgate_tokens SET safe_updates = true WHERE id = 'xyz-789...';
+--
+
+ALTER TABLE postgate_tokens
+    ADD COLUMN safe_updates boolean NOT NULL DEFAULT false;
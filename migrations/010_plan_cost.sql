@@ -0,0 +1,18 @@
+-- ============================================================================
+-- EXPLAIN AND PLAN COST CEILING
+-- ============================================================================
+--
+-- Tokens with the EXPLAIN permission can run EXPLAIN on their own queries
+-- (the explained statement needs its own permission too).
+--
+-- max_plan_cost: estimated total cost above which SELECT and DML statements
+-- are rejected before running. Postgate runs EXPLAIN (FORMAT JSON) first and
+-- compares the "Total Cost" of the plan. NULL disables the check.
+--
+-- Example:
+--   UPDATE postgate_databases SET max_plan_cost = 100000 WHERE id = 'xyz-789...';
+--
+
+ALTER TABLE postgate_databases
+    ADD COLUMN max_plan_cost double precision
+    CONSTRAINT positive_max_plan_cost CHECK (max_plan_cost > 0);
@@ -0,0 +1,19 @@
+-- ============================================================================
+-- PER-DATABASE CONCURRENCY LIMITS
+-- ============================================================================
+--
+-- max_concurrent_queries: transactions a database can run at the same time,
+-- so one busy tenant can't hold every connection of the shared pool. NULL
+-- means no limit other than the pool size.
+-- queue_timeout_ms: how long a query waits for a free slot before failing
+-- with TOO_MANY_CONCURRENT_QUERIES. NULL means the server default (5s).
+--
+-- Example:
+--   UPDATE postgate_databases SET max_concurrent_queries = 5 WHERE id = 'xyz-789...';
+--
+
+ALTER TABLE postgate_databases
+    ADD COLUMN max_concurrent_queries integer
+        CONSTRAINT positive_max_concurrent_queries CHECK (max_concurrent_queries > 0),
+    ADD COLUMN queue_timeout_ms integer
+        CONSTRAINT non_negative_queue_timeout_ms CHECK (queue_timeout_ms >= 0);
@@ -0,0 +1,15 @@
+-- ============================================================================
+-- DEDICATED BACKEND POOL SIZE
+-- ============================================================================
+--
+-- max_connections: size of the connection pool postgate opens to a dedicated
+-- backend. NULL means the default (10). Changing it (or connection_string)
+-- makes postgate rebuild the pool on the next query.
+--
+-- Example:
+--   UPDATE postgate_databases SET max_connections = 20 WHERE id = 'xyz-789...';
+--
+
+ALTER TABLE postgate_databases
+    ADD COLUMN max_connections integer
+        CONSTRAINT positive_max_connections CHECK (max_connections > 0);
@@ -0,0 +1,306 @@
+//! REST admin API for provisioning tenants, mounted under `/admin/v1`
+//!
+//! Authorized by admin-scoped tokens (tokens of the admin database). The HTTP method
+//! maps to the token permission it needs: GET requires SELECT, POST requires INSERT
+//! and DELETE requires DELETE.
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::{
+    ADMIN_DATABASE_ID, DatabaseBackend, DatabaseConfig, QueryLimits, SqlOperation, TokenPermission,
+};
+use crate::error::PostgateError;
+use crate::server::{AppState, authenticate_token};
+use crate::store::{TokenRestrictions, generate_schema_name};
+
+/// Same limit as the `name` column
+const MAX_NAME_LENGTH: usize = 100;
+
+fn default_max_rows() -> i32 {
+    1000
+}
+
+fn default_permissions() -> Vec<TokenPermission> {
+    TokenPermission::default_set().to_vec()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDatabaseRequest {
+    pub name: String,
+    /// Dedicated database to use instead of a schema on the shared one
+    #[serde(default)]
+    pub connection_string: Option<String>,
+    #[serde(default = "default_max_rows")]
+    pub max_rows: i32,
+}
+
+/// Database as exposed by the admin API (connection strings are never returned)
+#[derive(Debug, Serialize)]
+pub struct DatabaseResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub backend_type: &'static str,
+    pub schema_name: Option<String>,
+    pub max_rows: i32,
+    pub statement_timeout_ms: Option<i32>,
+    pub max_plan_cost: Option<f64>,
+    pub max_concurrent_queries: Option<i32>,
+    pub queue_timeout_ms: Option<i32>,
+    pub max_connections: Option<i32>,
+    #[serde(flatten)]
+    pub limits: QueryLimits,
+}
+
+impl From<DatabaseConfig> for DatabaseResponse {
+    fn from(db: DatabaseConfig) -> Self {
+        let (backend_type, schema_name) = match db.backend {
+            DatabaseBackend::Schema { schema_name } => ("schema", Some(schema_name)),
+            DatabaseBackend::Dedicated { .. } => ("dedicated", None),
+        };
+
+        DatabaseResponse {
+            id: db.id,
+            name: db.name,
+            backend_type,
+            schema_name,
+            max_rows: db.max_rows,
+            statement_timeout_ms: db.statement_timeout_ms,
+            max_plan_cost: db.max_plan_cost,
+            max_concurrent_queries: db.max_concurrent_queries,
+            queue_timeout_ms: db.queue_timeout_ms,
+            max_connections: db.max_connections,
+            limits: db.limits,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    #[serde(default = "default_permissions")]
+    pub permissions: Vec<TokenPermission>,
+    /// RFC 3339 expiry date, the token never expires if omitted
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Table, function and safe update rules, all optional
+    #[serde(flatten)]
+    pub restrictions: TokenRestrictions,
+}
+
+/// Newly created token. The secret is only returned once.
+#[derive(Debug, Serialize)]
+pub struct CreateTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub token: String,
+    pub permissions: Vec<TokenPermission>,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub restrictions: TokenRestrictions,
+}
+
+/// Check that the request comes from an admin token allowed to run `operation`
+async fn authorize(
+    req: &HttpRequest,
+    state: &AppState,
+    operation: SqlOperation,
+) -> Result<(), PostgateError> {
+    let token_info = authenticate_token(req, state).await?;
+
+    if token_info.database_id != ADMIN_DATABASE_ID {
+        return Err(PostgateError::Forbidden("Admin token required".to_string()));
+    }
+
+    // Same rule as /query: an empty set allows every operation
+    if !token_info.rules.allows_operation(operation) {
+        return Err(PostgateError::Forbidden(format!(
+            "Operation {} is not allowed",
+            operation
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<(), PostgateError> {
+    if name.trim().is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(PostgateError::BadRequest(format!(
+            "name must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn list_databases_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, PostgateError> {
+    authorize(&req, &state, SqlOperation::Select).await?;
+
+    let databases: Vec<DatabaseResponse> = state
+        .store
+        .list_databases()
+        .await?
+        .into_iter()
+        .map(DatabaseResponse::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(databases))
+}
+
+pub async fn create_database_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<CreateDatabaseRequest>,
+) -> Result<HttpResponse, PostgateError> {
+    authorize(&req, &state, SqlOperation::Insert).await?;
+
+    validate_name(&body.name)?;
+
+    if body.max_rows <= 0 {
+        return Err(PostgateError::BadRequest(
+            "max_rows must be positive".to_string(),
+        ));
+    }
+
+    let backend = match &body.connection_string {
+        Some(connection_string) => DatabaseBackend::Dedicated {
+            connection_string: connection_string.clone(),
+        },
+        None => DatabaseBackend::Schema {
+            schema_name: generate_schema_name(&body.name),
+        },
+    };
+
+    let database = state
+        .store
+        .create_database(&body.name, &backend, body.max_rows)
+        .await?;
+
+    Ok(HttpResponse::Created().json(DatabaseResponse::from(database)))
+}
+
+pub async fn get_database_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, PostgateError> {
+    authorize(&req, &state, SqlOperation::Select).await?;
+
+    let database = state.store.get_database(path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(DatabaseResponse::from(database)))
+}
+
+pub async fn delete_database_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, PostgateError> {
+    authorize(&req, &state, SqlOperation::Delete).await?;
+
+    let database_id = path.into_inner();
+    if database_id == ADMIN_DATABASE_ID {
+        return Err(PostgateError::BadRequest(
+            "The admin database can't be deleted".to_string(),
+        ));
+    }
+
+    state.store.delete_database(database_id).await?;
+    state.executor_pool.remove_dedicated_pool(database_id);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn list_tokens_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, PostgateError> {
+    authorize(&req, &state, SqlOperation::Select).await?;
+
+    let database = state.store.get_database(path.into_inner()).await?;
+    let tokens = state.store.list_tokens(database.id).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+pub async fn create_token_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    body: web::Json<CreateTokenRequest>,
+) -> Result<HttpResponse, PostgateError> {
+    authorize(&req, &state, SqlOperation::Insert).await?;
+
+    validate_name(&body.name)?;
+
+    if body
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(PostgateError::BadRequest(
+            "expires_at must be in the future".to_string(),
+        ));
+    }
+
+    let database = state.store.get_database(path.into_inner()).await?;
+    let body = body.into_inner();
+
+    let (id, token) = state
+        .store
+        .create_token(
+            database.id,
+            &body.name,
+            &body.permissions,
+            body.expires_at,
+            &body.restrictions,
+        )
+        .await?;
+
+    Ok(HttpResponse::Created().json(CreateTokenResponse {
+        id,
+        name: body.name,
+        token,
+        permissions: body.permissions,
+        expires_at: body.expires_at,
+        restrictions: body.restrictions,
+    }))
+}
+
+pub async fn delete_token_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, PostgateError> {
+    authorize(&req, &state, SqlOperation::Delete).await?;
+
+    let (database_id, token_id) = path.into_inner();
+    state
+        .store
+        .delete_database_token(database_id, token_id)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/databases", web::get().to(list_databases_handler))
+        .route("/databases", web::post().to(create_database_handler))
+        .route("/databases/{id}", web::get().to(get_database_handler))
+        .route("/databases/{id}", web::delete().to(delete_database_handler))
+        .route("/databases/{id}/tokens", web::get().to(list_tokens_handler))
+        .route(
+            "/databases/{id}/tokens",
+            web::post().to(create_token_handler),
+        )
+        .route(
+            "/databases/{id}/tokens/{token_id}",
+            web::delete().to(delete_token_handler),
+        );
+}
@@ -0,0 +1,301 @@
+//! Apache Arrow IPC stream encoding for query results
+//!
+//! Postgres types are mapped to native Arrow types so analytical clients can load
+//! results without going through JSON. Types without a dedicated mapping are sent
+//! as UTF-8 strings, like the JSON output does.
+
+use arrow_array::builder::{ArrayBuilder, BooleanBuilder, ListBuilder, StringBuilder};
+use arrow_array::types::{Float32Type, Float64Type, Int16Type, Int32Type, Int64Type};
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Date32Array, Decimal128Array, Float32Array, Float64Array,
+    Int16Array, Int32Array, Int64Array, ListArray, RecordBatch, StringArray,
+    Time64MicrosecondArray, TimestampMicrosecondArray,
+};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, Field, Schema};
+use chrono::{NaiveDate, Timelike};
+use serde_json::Value as JsonValue;
+use sqlx::postgres::{PgRow, PgTypeKind};
+use sqlx::{Column, Decode, Postgres, Row, Type};
+use std::sync::Arc;
+
+use crate::executor::{ExecutorError, QueryResult, decode_error, decode_numeric, get_column_value};
+
+/// Media type of an Arrow IPC stream
+pub const ARROW_STREAM_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+
+/// Largest precision supported by Decimal128
+const DECIMAL128_MAX_PRECISION: u8 = 38;
+
+/// Failure to encode a result as Arrow
+#[derive(Debug, thiserror::Error)]
+pub enum EncodeError {
+    /// A value couldn't be decoded; it is never sent as NULL instead
+    #[error(transparent)]
+    Decode(#[from] ExecutorError),
+
+    #[error(transparent)]
+    Arrow(#[from] ArrowError),
+}
+
+/// Encode a query result as an Arrow IPC stream with a single record batch
+pub fn encode_ipc_stream(result: &QueryResult) -> Result<Vec<u8>, EncodeError> {
+    let mut fields = Vec::with_capacity(result.columns.len());
+    let mut arrays = Vec::with_capacity(result.columns.len());
+
+    for (idx, column) in result.columns.iter().enumerate() {
+        let array = column_array(&result.rows, idx, &column.type_name)?;
+
+        fields.push(Field::new(
+            column.name.clone(),
+            array.data_type().clone(),
+            column.nullable.unwrap_or(true) || array.null_count() > 0,
+        ));
+        arrays.push(array);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new_with_options(
+        schema.clone(),
+        arrays,
+        &arrow_array::RecordBatchOptions::new().with_row_count(Some(result.rows.len())),
+    )?;
+
+    let mut writer = StreamWriter::try_new(Vec::new(), &schema)?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(writer.into_inner()?)
+}
+
+/// Decode one column of every row
+fn values<'r, T>(rows: &'r [PgRow], idx: usize) -> Result<Vec<Option<T>>, ExecutorError>
+where
+    T: Decode<'r, Postgres> + Type<Postgres>,
+{
+    rows.iter()
+        .map(|row| {
+            row.try_get::<Option<T>, _>(idx)
+                .map_err(|source| decode_error(row, idx, source))
+        })
+        .collect()
+}
+
+fn column_array(rows: &[PgRow], idx: usize, type_name: &str) -> Result<ArrayRef, EncodeError> {
+    let array: ArrayRef = match type_name {
+        "BOOL" => Arc::new(BooleanArray::from(values::<bool>(rows, idx)?)),
+        "INT2" => Arc::new(Int16Array::from(values::<i16>(rows, idx)?)),
+        "INT4" => Arc::new(Int32Array::from(values::<i32>(rows, idx)?)),
+        "INT8" => Arc::new(Int64Array::from(values::<i64>(rows, idx)?)),
+        "FLOAT4" => Arc::new(Float32Array::from(values::<f32>(rows, idx)?)),
+        "FLOAT8" => Arc::new(Float64Array::from(values::<f64>(rows, idx)?)),
+        "NUMERIC" => decimal_array(
+            rows.iter()
+                .map(|row| decode_numeric(row, idx))
+                .collect::<Result<_, _>>()?,
+        )?,
+        "TIMESTAMPTZ" => Arc::new(
+            TimestampMicrosecondArray::from(
+                values::<chrono::DateTime<chrono::Utc>>(rows, idx)?
+                    .into_iter()
+                    .map(|v| v.map(|v| v.timestamp_micros()))
+                    .collect::<Vec<_>>(),
+            )
+            .with_timezone_utc(),
+        ),
+        "TIMESTAMP" => Arc::new(TimestampMicrosecondArray::from(
+            values::<chrono::NaiveDateTime>(rows, idx)?
+                .into_iter()
+                .map(|v| v.map(|v| v.and_utc().timestamp_micros()))
+                .collect::<Vec<_>>(),
+        )),
+        "DATE" => {
+            let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date");
+            Arc::new(Date32Array::from(
+                values::<NaiveDate>(rows, idx)?
+                    .into_iter()
+                    .map(|v| v.map(|v| (v - epoch).num_days() as i32))
+                    .collect::<Vec<_>>(),
+            ))
+        }
+        "TIME" => Arc::new(Time64MicrosecondArray::from(
+            values::<chrono::NaiveTime>(rows, idx)?
+                .into_iter()
+                .map(|v| {
+                    v.map(|v| {
+                        v.num_seconds_from_midnight() as i64 * 1_000_000
+                            + (v.nanosecond() / 1_000) as i64
+                    })
+                })
+                .collect::<Vec<_>>(),
+        )),
+        "UUID" => Arc::new(StringArray::from(
+            values::<uuid::Uuid>(rows, idx)?
+                .into_iter()
+                .map(|v| v.map(|v| v.to_string()))
+                .collect::<Vec<_>>(),
+        )),
+        "JSON" | "JSONB" => Arc::new(StringArray::from(
+            values::<serde_json::Value>(rows, idx)?
+                .into_iter()
+                .map(|v| v.map(|v| v.to_string()))
+                .collect::<Vec<_>>(),
+        )),
+        "BOOL[]" => list_array(
+            rows,
+            idx,
+            BooleanBuilder::new(),
+            BooleanBuilder::append_option,
+        )?,
+        "INT2[]" => Arc::new(ListArray::from_iter_primitive::<Int16Type, _, _>(values::<
+            Vec<Option<i16>>,
+        >(
+            rows, idx,
+        )?)),
+        "INT4[]" => Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(values::<
+            Vec<Option<i32>>,
+        >(
+            rows, idx,
+        )?)),
+        "INT8[]" => Arc::new(ListArray::from_iter_primitive::<Int64Type, _, _>(values::<
+            Vec<Option<i64>>,
+        >(
+            rows, idx,
+        )?)),
+        "FLOAT4[]" => Arc::new(ListArray::from_iter_primitive::<Float32Type, _, _>(
+            values::<Vec<Option<f32>>>(rows, idx)?,
+        )),
+        "FLOAT8[]" => Arc::new(ListArray::from_iter_primitive::<Float64Type, _, _>(
+            values::<Vec<Option<f64>>>(rows, idx)?,
+        )),
+        "TEXT[]" | "VARCHAR[]" | "BPCHAR[]" | "NAME[]" => {
+            list_array::<String, _>(rows, idx, StringBuilder::new(), |builder, item| {
+                builder.append_option(item)
+            })?
+        }
+        // Text and everything else: the strings the JSON output uses
+        _ if is_array_column(rows, idx, type_name) => string_list_array(rows, idx)?,
+        _ => string_array(rows, idx)?,
+    };
+
+    Ok(array)
+}
+
+/// Build a list column from Postgres arrays decoded as `Vec<Option<T>>`
+fn list_array<'r, T, B>(
+    rows: &'r [PgRow],
+    idx: usize,
+    item_builder: B,
+    append_item: fn(&mut B, Option<T>),
+) -> Result<ArrayRef, ExecutorError>
+where
+    Vec<Option<T>>: Decode<'r, Postgres> + Type<Postgres>,
+    B: ArrayBuilder,
+{
+    let mut builder = ListBuilder::new(item_builder);
+
+    for value in values::<Vec<Option<T>>>(rows, idx)? {
+        match value {
+            Some(items) => {
+                for item in items {
+                    append_item(builder.values(), item);
+                }
+                builder.append(true);
+            }
+            None => builder.append_null(),
+        }
+    }
+
+    Ok(Arc::new(builder.finish()))
+}
+
+/// Postgres arrays are judged by the decoded type, since enum arrays are named
+/// after the internal `_name` type rather than `name[]`
+fn is_array_column(rows: &[PgRow], idx: usize, type_name: &str) -> bool {
+    match rows.first() {
+        Some(row) => matches!(row.column(idx).type_info().kind(), PgTypeKind::Array(_)),
+        None => type_name.ends_with("[]"),
+    }
+}
+
+fn json_values(rows: &[PgRow], idx: usize) -> Result<Vec<JsonValue>, ExecutorError> {
+    rows.iter()
+        .map(|row| get_column_value(row, idx, row.column(idx).type_info()))
+        .collect()
+}
+
+/// JSON strings as-is, other values serialized
+fn json_to_string(value: JsonValue) -> Option<String> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::String(s) => Some(s),
+        other => Some(other.to_string()),
+    }
+}
+
+fn string_array(rows: &[PgRow], idx: usize) -> Result<ArrayRef, ExecutorError> {
+    let values: Vec<Option<String>> = json_values(rows, idx)?
+        .into_iter()
+        .map(json_to_string)
+        .collect();
+
+    Ok(Arc::new(StringArray::from(values)))
+}
+
+fn string_list_array(rows: &[PgRow], idx: usize) -> Result<ArrayRef, ExecutorError> {
+    let mut builder = ListBuilder::new(StringBuilder::new());
+
+    for value in json_values(rows, idx)? {
+        match value {
+            JsonValue::Array(items) => {
+                for item in items {
+                    builder.values().append_option(json_to_string(item));
+                }
+                builder.append(true);
+            }
+            _ => builder.append_null(),
+        }
+    }
+
+    Ok(Arc::new(builder.finish()))
+}
+
+/// NUMERIC as Decimal128, with the largest scale found in the column.
+/// Falls back to exact strings when a value doesn't fit (more than 38 digits,
+/// NaN or infinity).
+fn decimal_array(values: Vec<Option<String>>) -> Result<ArrayRef, ArrowError> {
+    let scale = values
+        .iter()
+        .flatten()
+        .map(|v| v.split_once('.').map_or(0, |(_, fraction)| fraction.len()))
+        .max()
+        .unwrap_or(0);
+
+    let unscaled: Option<Vec<Option<i128>>> = values
+        .iter()
+        .map(|value| match value {
+            Some(v) => unscaled_decimal(v, scale).map(Some),
+            None => Some(None),
+        })
+        .collect();
+
+    match unscaled {
+        Some(unscaled) if scale <= DECIMAL128_MAX_PRECISION as usize => Ok(Arc::new(
+            Decimal128Array::from(unscaled)
+                .with_precision_and_scale(DECIMAL128_MAX_PRECISION, scale as i8)?,
+        )),
+        _ => Ok(Arc::new(StringArray::from(values))),
+    }
+}
+
+/// `"-12.5"` with scale 3 becomes `-12500`
+fn unscaled_decimal(value: &str, scale: usize) -> Option<i128> {
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let digits = format!("{integer}{fraction:0<scale$}");
+    let digit_count = digits.trim_start_matches('-').len();
+
+    if digit_count > DECIMAL128_MAX_PRECISION as usize {
+        return None;
+    }
+
+    digits.parse().ok()
+}
@@ -3,11 +3,11 @@
 //! Tokens are formatted as: pg_<random_64_hex_chars>
 //! They are validated by hashing and comparing with stored hash
 
-use std::collections::HashSet;
+use chrono::{DateTime, Utc};
 use thiserror::Error;
 use uuid::Uuid;
 
-use crate::config::SqlOperation;
+use crate::config::QueryRules;
 use crate::token::{hash_token, is_valid_format};
 
 #[derive(Debug, Error)]
@@ -30,7 +30,10 @@ pub enum AuthError {
 pub struct TokenInfo {
     pub database_id: Uuid,
     pub token_id: Uuid,
-    pub allowed_operations: HashSet<SqlOperation>,
+    /// Allowed operations and tables
+    pub rules: QueryRules,
+    /// None if the token never expires
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 /// Extract token from Authorization header
@@ -0,0 +1,110 @@
+//! Cancel statements in Postgres when postgate stops waiting for them
+//!
+//! Dropping a query future (timeout, client disconnect) doesn't stop the backend:
+//! the statement keeps running and holds its connection. A [`CancellableTransaction`]
+//! dropped while a statement is in flight calls `pg_cancel_backend` for it, and only
+//! releases the connection once the cancel went through, so that the cancel can't
+//! hit the next statement run on the same connection.
+//!
+//! The transaction also holds its database's concurrency slot, if any, released
+//! once the connection is back (after the cancel and rollback).
+
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPool};
+use sqlx::{Connection, Postgres, Transaction};
+use std::sync::Arc;
+use tokio::sync::OwnedSemaphorePermit;
+
+pub(crate) struct CancellableTransaction {
+    tx: Option<Transaction<'static, Postgres>>,
+    backend_pid: i32,
+    /// Used to open a separate connection for the cancel, the pool may be exhausted
+    connect_options: Arc<PgConnectOptions>,
+    in_flight: bool,
+    slot: Option<OwnedSemaphorePermit>,
+}
+
+impl CancellableTransaction {
+    pub fn new(
+        tx: Transaction<'static, Postgres>,
+        backend_pid: i32,
+        pool: &PgPool,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Self {
+        Self {
+            tx: Some(tx),
+            backend_pid,
+            connect_options: pool.connect_options(),
+            in_flight: false,
+            slot,
+        }
+    }
+
+    /// Run statements on the transaction. If this future is dropped before it
+    /// completes, the running statement is canceled.
+    pub async fn run<T>(&mut self, f: impl AsyncFnOnce(&mut PgConnection) -> T) -> T {
+        self.in_flight = true;
+        let output = f(self.connection()).await;
+        self.in_flight = false;
+
+        output
+    }
+
+    pub async fn commit(mut self) -> Result<(), sqlx::Error> {
+        self.connection_owned().commit().await
+    }
+
+    /// Cancel whatever may still be running (e.g. a partially read result set)
+    /// and roll back
+    pub fn cancel(mut self) {
+        self.in_flight = true;
+    }
+
+    fn connection(&mut self) -> &mut PgConnection {
+        self.tx.as_mut().expect("transaction already consumed")
+    }
+
+    fn connection_owned(&mut self) -> Transaction<'static, Postgres> {
+        self.tx.take().expect("transaction already consumed")
+    }
+}
+
+impl Drop for CancellableTransaction {
+    fn drop(&mut self) {
+        if !self.in_flight {
+            return;
+        }
+
+        let (Some(tx), Ok(runtime)) = (self.tx.take(), tokio::runtime::Handle::try_current())
+        else {
+            return;
+        };
+
+        let connect_options = self.connect_options.clone();
+        let backend_pid = self.backend_pid;
+        let slot = self.slot.take();
+
+        runtime.spawn(async move {
+            if let Err(e) = cancel_backend(&connect_options, backend_pid).await {
+                log::warn!("Failed to cancel backend {}: {}", backend_pid, e);
+            }
+
+            // Rolled back once the canceled statement has returned
+            drop(tx);
+            drop(slot);
+        });
+    }
+}
+
+async fn cancel_backend(
+    connect_options: &PgConnectOptions,
+    backend_pid: i32,
+) -> Result<(), sqlx::Error> {
+    let mut conn = PgConnection::connect_with(connect_options).await?;
+
+    sqlx::query("SELECT pg_cancel_backend($1)")
+        .bind(backend_pid)
+        .execute(&mut conn)
+        .await?;
+
+    conn.close().await
+}
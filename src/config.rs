@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Seed database whose tokens are admin-scoped (public schema, manages tenants)
+pub const ADMIN_DATABASE_ID: Uuid = Uuid::nil();
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -37,15 +41,55 @@ pub struct DatabaseConfig {
     pub name: String,
     pub backend: DatabaseBackend,
     pub max_rows: i32,
+    /// Query timeout ceiling in milliseconds (None = server default)
+    pub statement_timeout_ms: Option<i32>,
+    /// Estimated cost ceiling, checked with EXPLAIN before running (None = no check)
+    pub max_plan_cost: Option<f64>,
+    /// Transactions allowed to run at the same time (None = no limit)
+    pub max_concurrent_queries: Option<i32>,
+    /// Wait for a free slot before giving up, in milliseconds (None = server default)
+    pub queue_timeout_ms: Option<i32>,
+    /// Connections of the dedicated backend pool (None = default)
+    pub max_connections: Option<i32>,
+    pub limits: QueryLimits,
+}
+
+/// Query complexity limits, checked at parse time (None = no limit)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct QueryLimits {
+    /// Joins in the whole statement, comma-separated FROM items included
+    pub max_joins: Option<i32>,
+    /// Nesting depth of subqueries and CTEs
+    pub max_subquery_depth: Option<i32>,
+    /// Values in a single IN (...) list
+    pub max_in_list: Option<i32>,
+    /// Distinct $n parameters
+    pub max_params: Option<i32>,
+    /// Length of the SQL text in bytes
+    pub max_sql_length: Option<i32>,
 }
 
-/// Rules used for parsing/validating queries
-/// allowed_operations comes from token, tables rules are system-level
+/// Rules used for parsing/validating queries, from the token
+/// allowed_operations: empty allows every operation
+/// allowed_tables: None allows every table, denied_tables wins over allowed_tables
+/// allowed_functions lifts the default function denylist, denied_functions extends it
+/// safe_updates rejects UPDATE and DELETE that don't filter rows
+/// limits come from the token's database
 #[derive(Debug, Clone, Default)]
 pub struct QueryRules {
     pub allowed_operations: HashSet<SqlOperation>,
     pub allowed_tables: Option<HashSet<String>>,
     pub denied_tables: HashSet<String>,
+    pub allowed_functions: HashSet<String>,
+    pub denied_functions: HashSet<String>,
+    pub safe_updates: bool,
+    pub limits: QueryLimits,
+}
+
+impl QueryRules {
+    pub fn allows_operation(&self, operation: SqlOperation) -> bool {
+        self.allowed_operations.is_empty() || self.allowed_operations.contains(&operation)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -58,6 +102,7 @@ pub enum SqlOperation {
     Create,
     Alter,
     Drop,
+    Explain,
 }
 
 impl SqlOperation {
@@ -70,9 +115,21 @@ impl SqlOperation {
             SqlOperation::Create => "CREATE",
             SqlOperation::Alter => "ALTER",
             SqlOperation::Drop => "DROP",
+            SqlOperation::Explain => "EXPLAIN",
         }
     }
 
+    /// Returns true for SELECT and DML, whose plan can be checked with EXPLAIN
+    pub fn is_explainable(&self) -> bool {
+        matches!(
+            self,
+            SqlOperation::Select
+                | SqlOperation::Insert
+                | SqlOperation::Update
+                | SqlOperation::Delete
+        )
+    }
+
     /// Returns true for DDL operations (CREATE, ALTER, DROP)
     /// DDL statements don't return rows and need special handling
     pub fn is_ddl(&self) -> bool {
@@ -101,6 +158,7 @@ pub enum TokenPermission {
     Create,
     Alter,
     Drop,
+    Explain,
 }
 
 impl TokenPermission {
@@ -113,6 +171,7 @@ impl TokenPermission {
             TokenPermission::Create => "CREATE",
             TokenPermission::Alter => "ALTER",
             TokenPermission::Drop => "DROP",
+            TokenPermission::Explain => "EXPLAIN",
         }
     }
 
@@ -126,7 +185,7 @@ impl TokenPermission {
         ]
     }
 
-    /// Tenant permissions (DML + DDL)
+    /// Tenant permissions (DML + DDL + EXPLAIN)
     pub fn tenant_set() -> &'static [TokenPermission] {
         &[
             TokenPermission::Select,
@@ -136,6 +195,7 @@ impl TokenPermission {
             TokenPermission::Create,
             TokenPermission::Alter,
             TokenPermission::Drop,
+            TokenPermission::Explain,
         ]
     }
 }
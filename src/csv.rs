@@ -0,0 +1,84 @@
+//! CSV encoding for query results (RFC 4180)
+//!
+//! Values go through the same per-type conversion as the JSON output. NULL is
+//! an empty field, strings are written as-is, and everything else (numbers,
+//! booleans, JSON documents) uses its JSON text.
+
+use serde_json::Value as JsonValue;
+
+use crate::executor::{ExecutorError, QueryResult, row_to_values};
+
+/// Media type of a CSV response
+pub const CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+
+/// Encode a query result as CSV with a header row, columns in select order
+pub fn encode_csv(result: QueryResult) -> Result<String, ExecutorError> {
+    let mut out = String::new();
+
+    let header: Vec<String> = result
+        .columns
+        .iter()
+        .map(|column| escape_field(&column.name))
+        .collect();
+    push_record(&mut out, header);
+
+    for row in result.rows {
+        let fields = row_to_values(row)?.iter().map(format_value).collect();
+        push_record(&mut out, fields);
+    }
+
+    Ok(out)
+}
+
+fn push_record(out: &mut String, fields: Vec<String>) {
+    out.push_str(&fields.join(","));
+    out.push_str("\r\n");
+}
+
+fn format_value(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => String::new(),
+        JsonValue::String(s) => escape_field(s),
+        other => escape_field(&other.to_string()),
+    }
+}
+
+/// Quote a field when it contains a delimiter, quote or line break
+fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        assert_eq!(escape_field("Alice"), "Alice");
+        assert_eq!(escape_field(""), "");
+    }
+
+    #[test]
+    fn special_characters_are_quoted() {
+        assert_eq!(escape_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_field("line\nbreak"), "\"line\nbreak\"");
+        assert_eq!(escape_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn values_use_json_conversion() {
+        assert_eq!(format_value(&JsonValue::Null), "");
+        assert_eq!(format_value(&json!(42)), "42");
+        assert_eq!(format_value(&json!(true)), "true");
+        assert_eq!(format_value(&json!("x")), "x");
+        assert_eq!(
+            format_value(&json!({"a": 1, "b": 2})),
+            "\"{\"\"a\"\":1,\"\"b\"\":2}\""
+        );
+    }
+}
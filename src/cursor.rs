@@ -0,0 +1,166 @@
+//! Server-side cursors for paginating large results across requests
+//!
+//! Each open cursor keeps its own transaction (and so its own connection), and its
+//! database's concurrency slot if any, until it is exhausted, closed, or left idle
+//! for longer than [`CURSOR_IDLE_TIMEOUT`]. Dropping a cursor cancels a FETCH still
+//! running, rolls its transaction back, returns the connection and frees the slot.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::cancel::CancellableTransaction;
+
+/// Cursors not fetched for this long are closed
+pub const CURSOR_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Open cursors allowed per database, each one holds a connection
+pub const MAX_CURSORS_PER_DATABASE: usize = 5;
+
+/// How often idle cursors are looked for
+const REAP_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize)]
+pub struct OpenCursorResponse {
+    pub cursor_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FetchCursorRequest {
+    pub cursor_id: Uuid,
+    /// Number of rows to fetch, capped by the database max_rows
+    pub fetch: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloseCursorRequest {
+    pub cursor_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CursorPage {
+    pub rows: Vec<HashMap<String, JsonValue>>,
+    pub row_count: usize,
+    /// True once the result set is exhausted; the cursor is closed at that point
+    pub done: bool,
+}
+
+/// Name of the cursor in Postgres. Every cursor has its own transaction,
+/// so the name never clashes.
+pub(crate) const CURSOR_NAME: &str = "postgate_cursor";
+
+/// An open cursor: the transaction it was declared in
+pub(crate) struct Cursor {
+    pub tx: CancellableTransaction,
+    last_used: Instant,
+}
+
+impl Cursor {
+    pub fn new(tx: CancellableTransaction) -> Self {
+        Self {
+            tx,
+            last_used: Instant::now(),
+        }
+    }
+
+    pub fn touch(&mut self) {
+        self.last_used = Instant::now();
+    }
+}
+
+struct Entry {
+    database_id: Uuid,
+    cursor: Arc<Mutex<Cursor>>,
+}
+
+/// Open cursors by id
+#[derive(Default)]
+pub(crate) struct CursorRegistry {
+    cursors: std::sync::Mutex<HashMap<Uuid, Entry>>,
+}
+
+impl CursorRegistry {
+    /// Create a registry and start closing idle cursors in the background.
+    /// The task stops once the registry is dropped.
+    pub fn start() -> Arc<Self> {
+        let registry = Arc::new(Self::default());
+
+        tokio::spawn(reap_idle_cursors(Arc::downgrade(&registry)));
+
+        registry
+    }
+
+    /// Register a cursor, unless its database already has too many open.
+    /// On refusal the cursor is dropped, which rolls its transaction back.
+    pub fn insert(&self, database_id: Uuid, cursor: Cursor) -> Option<Uuid> {
+        let mut cursors = self.cursors.lock().expect("cursor registry poisoned");
+
+        let open = cursors
+            .values()
+            .filter(|entry| entry.database_id == database_id)
+            .count();
+        if open >= MAX_CURSORS_PER_DATABASE {
+            return None;
+        }
+
+        let id = Uuid::new_v4();
+        let cursor = Arc::new(Mutex::new(cursor));
+        cursors.insert(
+            id,
+            Entry {
+                database_id,
+                cursor,
+            },
+        );
+        Some(id)
+    }
+
+    /// Look up a cursor opened for this database
+    pub fn get(&self, database_id: Uuid, id: Uuid) -> Option<Arc<Mutex<Cursor>>> {
+        let cursors = self.cursors.lock().expect("cursor registry poisoned");
+
+        cursors
+            .get(&id)
+            .filter(|entry| entry.database_id == database_id)
+            .map(|entry| entry.cursor.clone())
+    }
+
+    /// Unregister a cursor opened for this database
+    pub fn remove(&self, database_id: Uuid, id: Uuid) -> Option<Arc<Mutex<Cursor>>> {
+        let mut cursors = self.cursors.lock().expect("cursor registry poisoned");
+
+        match cursors.get(&id) {
+            Some(entry) if entry.database_id == database_id => {
+                cursors.remove(&id).map(|entry| entry.cursor)
+            }
+            _ => None,
+        }
+    }
+
+    /// Drop cursors idle for longer than the timeout. Cursors in use are kept.
+    fn remove_idle(&self) {
+        let mut cursors = self.cursors.lock().expect("cursor registry poisoned");
+
+        cursors.retain(|_, entry| match entry.cursor.try_lock() {
+            Ok(cursor) => cursor.last_used.elapsed() < CURSOR_IDLE_TIMEOUT,
+            Err(_) => true,
+        });
+    }
+}
+
+async fn reap_idle_cursors(registry: Weak<CursorRegistry>) {
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match registry.upgrade() {
+            Some(registry) => registry.remove_idle(),
+            None => return,
+        }
+    }
+}
@@ -3,6 +3,7 @@ use uuid::Uuid;
 
 use crate::executor::ExecutorError;
 use crate::parser::ParseError;
+use crate::store::StoreError;
 
 #[derive(Debug, Error)]
 pub enum PostgateError {
@@ -12,6 +13,9 @@ pub enum PostgateError {
     #[error("Execution error: {0}")]
     Executor(#[from] ExecutorError),
 
+    #[error("Store error: {0}")]
+    Store(#[from] StoreError),
+
     #[error("Database not found: {0}")]
     DatabaseNotFound(Uuid),
 
@@ -21,6 +25,15 @@ pub enum PostgateError {
     #[error("Invalid authorization header")]
     InvalidAuth,
 
+    #[error("Token expired")]
+    TokenExpired,
+
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -31,45 +44,337 @@ mod server_impl {
     use actix_web::{HttpResponse, ResponseError};
     use serde::Serialize;
 
+    /// Replaces tenant schema names that aren't part of a qualified name
+    const SCHEMA_PLACEHOLDER: &str = "<schema>";
+
+    /// SQLSTATEs of references to something that doesn't exist: table, column,
+    /// function, type or other object, parameter
+    const UNDEFINED_OBJECT_CODES: &[&str] = &["42P01", "42703", "42883", "42704", "42P02"];
+
     #[derive(Serialize)]
-    struct ErrorResponse {
-        error: String,
-        code: &'static str,
+    pub(crate) struct ErrorResponse {
+        pub error: String,
+        pub code: &'static str,
+        /// Logged with the full error, for support
+        pub request_id: Uuid,
+        #[serde(flatten)]
+        pub database: Option<DatabaseErrorDetails>,
     }
 
-    impl ResponseError for PostgateError {
-        fn error_response(&self) -> HttpResponse {
-            let (status, code) = match self {
+    /// Fields of an error raised by Postgres, so clients don't have to parse messages
+    #[derive(Serialize)]
+    pub(crate) struct DatabaseErrorDetails {
+        pub sqlstate: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub constraint: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub table: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub column: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub detail: Option<String>,
+    }
+
+    impl PostgateError {
+        /// HTTP status and machine-readable code reported to clients
+        pub fn status_and_code(&self) -> (actix_web::http::StatusCode, &'static str) {
+            match self {
+                PostgateError::Parse(ParseError::LimitExceeded { .. }) => (
+                    actix_web::http::StatusCode::BAD_REQUEST,
+                    "QUERY_TOO_COMPLEX",
+                ),
                 PostgateError::Parse(_) => {
                     (actix_web::http::StatusCode::BAD_REQUEST, "PARSE_ERROR")
                 }
-                PostgateError::Executor(ExecutorError::Timeout) => {
+                PostgateError::Executor(e) if e.is_timeout() => {
                     (actix_web::http::StatusCode::GATEWAY_TIMEOUT, "TIMEOUT")
                 }
                 PostgateError::Executor(ExecutorError::RowLimitExceeded(_)) => (
                     actix_web::http::StatusCode::BAD_REQUEST,
                     "ROW_LIMIT_EXCEEDED",
                 ),
-                PostgateError::Executor(_) => (
+                PostgateError::Executor(ExecutorError::InvalidParam { .. }) => (
+                    actix_web::http::StatusCode::BAD_REQUEST,
+                    "INVALID_PARAMETER",
+                ),
+                PostgateError::Executor(ExecutorError::QueryTooExpensive { .. }) => (
+                    actix_web::http::StatusCode::BAD_REQUEST,
+                    "QUERY_TOO_EXPENSIVE",
+                ),
+                PostgateError::Executor(ExecutorError::CursorNotFound(_)) => {
+                    (actix_web::http::StatusCode::NOT_FOUND, "CURSOR_NOT_FOUND")
+                }
+                PostgateError::Executor(ExecutorError::TooManyCursors(_)) => (
+                    actix_web::http::StatusCode::TOO_MANY_REQUESTS,
+                    "TOO_MANY_CURSORS",
+                ),
+                PostgateError::Executor(ExecutorError::Decode { .. }) => (
+                    actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "DECODE_ERROR",
+                ),
+                PostgateError::Executor(ExecutorError::TooManyConcurrentQueries(_)) => (
+                    actix_web::http::StatusCode::TOO_MANY_REQUESTS,
+                    "TOO_MANY_CONCURRENT_QUERIES",
+                ),
+                PostgateError::Executor(e) => match e.database_error().map(|e| e.code()) {
+                    Some("23505") => (actix_web::http::StatusCode::CONFLICT, "UNIQUE_VIOLATION"),
+                    Some("23503") => (
+                        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY,
+                        "FOREIGN_KEY_VIOLATION",
+                    ),
+                    Some("23514") => (
+                        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY,
+                        "CHECK_VIOLATION",
+                    ),
+                    Some("23502") => (
+                        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY,
+                        "NOT_NULL_VIOLATION",
+                    ),
+                    Some("42601") => (actix_web::http::StatusCode::BAD_REQUEST, "SYNTAX_ERROR"),
+                    Some(code) if UNDEFINED_OBJECT_CODES.contains(&code) => {
+                        (actix_web::http::StatusCode::BAD_REQUEST, "UNDEFINED_OBJECT")
+                    }
+                    Some("42501") => (
+                        actix_web::http::StatusCode::FORBIDDEN,
+                        "INSUFFICIENT_PRIVILEGE",
+                    ),
+                    // Rest of class 42: type mismatches, ambiguous columns, grouping errors...
+                    Some(code) if code.starts_with("42") => {
+                        (actix_web::http::StatusCode::BAD_REQUEST, "INVALID_QUERY")
+                    }
+                    _ => (
+                        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        "DATABASE_ERROR",
+                    ),
+                },
+                PostgateError::Store(StoreError::NotFound(_)) => {
+                    (actix_web::http::StatusCode::NOT_FOUND, "DATABASE_NOT_FOUND")
+                }
+                PostgateError::Store(StoreError::TokenNotFound) => {
+                    (actix_web::http::StatusCode::NOT_FOUND, "TOKEN_NOT_FOUND")
+                }
+                PostgateError::Store(_) => (
                     actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
                     "DATABASE_ERROR",
                 ),
                 PostgateError::DatabaseNotFound(_) => {
                     (actix_web::http::StatusCode::NOT_FOUND, "DATABASE_NOT_FOUND")
                 }
+                PostgateError::BadRequest(_) => {
+                    (actix_web::http::StatusCode::BAD_REQUEST, "BAD_REQUEST")
+                }
+                PostgateError::Forbidden(_) => {
+                    (actix_web::http::StatusCode::FORBIDDEN, "FORBIDDEN")
+                }
                 PostgateError::MissingAuth | PostgateError::InvalidAuth => {
                     (actix_web::http::StatusCode::UNAUTHORIZED, "UNAUTHORIZED")
                 }
+                PostgateError::TokenExpired => {
+                    (actix_web::http::StatusCode::UNAUTHORIZED, "TOKEN_EXPIRED")
+                }
                 PostgateError::Internal(_) => (
                     actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
                     "INTERNAL_ERROR",
                 ),
+            }
+        }
+
+        pub(crate) fn to_error_response(&self) -> ErrorResponse {
+            let database = match self {
+                PostgateError::Executor(e) if !self.is_connection_failure() => e.database_error(),
+                _ => None,
             };
 
-            HttpResponse::build(status).json(ErrorResponse {
-                error: self.to_string(),
+            let (status, code) = self.status_and_code();
+
+            // Clients only get the redacted message, the full one is logged under the id
+            let request_id = Uuid::new_v4();
+            if status.is_server_error() {
+                log::error!("Request {} failed: {}", request_id, self);
+            } else {
+                log::info!("Request {} failed: {}", request_id, self);
+            }
+
+            ErrorResponse {
+                error: self.public_message(),
                 code,
-            })
+                request_id,
+                database: database.map(|e| DatabaseErrorDetails {
+                    sqlstate: e.code().to_string(),
+                    constraint: e.constraint().map(str::to_string),
+                    table: e.table().map(str::to_string),
+                    column: e.column().map(str::to_string),
+                    detail: e.detail().map(redact_schema_names),
+                }),
+            }
+        }
+
+        /// Message safe to show tenants: no generated schema names, and nothing
+        /// about how postgate connects to Postgres (hosts, users, TLS)
+        fn public_message(&self) -> String {
+            if self.is_connection_failure() {
+                return "Database connection error".to_string();
+            }
+
+            redact_schema_names(&self.to_string())
+        }
+
+        /// Postgres couldn't be reached or refused the login. These errors name
+        /// hosts and users, so they stay in the logs.
+        fn is_connection_failure(&self) -> bool {
+            match self {
+                PostgateError::Executor(ExecutorError::Connection(_)) => true,
+                PostgateError::Executor(ExecutorError::Database(e))
+                | PostgateError::Store(StoreError::Database(e)) => is_connection_error(e),
+                _ => false,
+            }
+        }
+    }
+
+    fn is_connection_error(error: &sqlx::Error) -> bool {
+        match error {
+            // Class 08: connection exception, class 28: invalid authorization
+            sqlx::Error::Database(e) => e
+                .code()
+                .is_some_and(|code| code.starts_with("08") || code.starts_with("28")),
+            _ => matches!(
+                error,
+                sqlx::Error::Configuration(_)
+                    | sqlx::Error::Io(_)
+                    | sqlx::Error::Tls(_)
+                    | sqlx::Error::Protocol(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::PoolClosed
+                    | sqlx::Error::WorkerCrashed
+            ),
+        }
+    }
+
+    /// Prefixes of generated tenant schemas and the length of the hex id that follows:
+    /// `db_<8 hex>_<name>` (`generate_schema_name`) and `tenant_<32 hex>_<name>`
+    /// (`create_tenant_database()`)
+    const SCHEMA_PREFIXES: &[(&str, usize)] = &[("db_", 8), ("tenant_", 32)];
+
+    /// Strip tenant schemas (see [`SCHEMA_PREFIXES`]) from qualified names, `"db_1a2b3c4d_app"."users"` becoming `"users"`, and hide
+    /// the ones standing alone.
+    fn redact_schema_names(message: &str) -> String {
+        let mut redacted = String::with_capacity(message.len());
+        let mut rest = message;
+        let mut previous: Option<char> = None;
+
+        while let Some(c) = rest.chars().next() {
+            let at_word_start = previous.is_none_or(|p| !(p.is_alphanumeric() || p == '_'));
+
+            if let Some(len) = schema_name_len(rest).filter(|_| at_word_start) {
+                let after = &rest[len..];
+
+                if previous == Some('"') && after.starts_with("\".") {
+                    redacted.pop();
+                    rest = &after[2..];
+                } else if let Some(after) = after.strip_prefix('.') {
+                    rest = after;
+                } else {
+                    redacted.push_str(SCHEMA_PLACEHOLDER);
+                    rest = after;
+                }
+
+                previous = redacted.chars().next_back();
+                continue;
+            }
+
+            redacted.push(c);
+            rest = &rest[c.len_utf8()..];
+            previous = Some(c);
+        }
+
+        redacted
+    }
+
+    /// Length of the tenant schema name `text` starts with, if any
+    fn schema_name_len(text: &str) -> Option<usize> {
+        SCHEMA_PREFIXES.iter().find_map(|&(prefix, id_len)| {
+            let tail = text.strip_prefix(prefix)?;
+            let hex = tail.get(..id_len)?;
+            if !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+                return None;
+            }
+
+            let name = tail[id_len..].strip_prefix('_')?;
+            let name_len: usize = name
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .map(char::len_utf8)
+                .sum();
+
+            Some(prefix.len() + id_len + 1 + name_len)
+        })
+    }
+
+    impl ResponseError for PostgateError {
+        fn status_code(&self) -> actix_web::http::StatusCode {
+            self.status_and_code().0
+        }
+
+        fn error_response(&self) -> HttpResponse {
+            HttpResponse::build(self.status_code()).json(self.to_error_response())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn qualified_names_lose_their_schema() {
+            assert_eq!(
+                redact_schema_names(r#"relation "db_1a2b3c4d_my_app.users" does not exist"#),
+                r#"relation "users" does not exist"#
+            );
+            assert_eq!(
+                redact_schema_names(r#"permission denied for "db_1a2b3c4d_app"."users""#),
+                r#"permission denied for "users""#
+            );
+            assert_eq!(
+                redact_schema_names(
+                    r#"relation "tenant_0123456789abcdef0123456789abcdef_My_App.users" is missing"#
+                ),
+                r#"relation "users" is missing"#
+            );
+        }
+
+        #[test]
+        fn standalone_schema_names_are_hidden() {
+            assert_eq!(
+                redact_schema_names(r#"schema "db_1a2b3c4d_app" does not exist"#),
+                r#"schema "<schema>" does not exist"#
+            );
+            assert_eq!(
+                redact_schema_names("no access to db_1a2b3c4d_app"),
+                "no access to <schema>"
+            );
+        }
+
+        #[test]
+        fn other_names_are_kept() {
+            for message in [
+                r#"relation "db_users" does not exist"#,
+                r#"relation "my_db_1a2b3c4d_x" does not exist"#,
+                r#"relation "db_1A2B3C4D_x" does not exist"#,
+                r#"relation "tenant_1a2b3c4d_x" does not exist"#,
+                "column \"ünïcode\" does not exist",
+            ] {
+                assert_eq!(redact_schema_names(message), message);
+            }
+        }
+
+        #[test]
+        fn connection_errors_are_hidden() {
+            let error = PostgateError::Executor(ExecutorError::Database(sqlx::Error::Io(
+                std::io::Error::other("connect to db.internal:5432 as admin failed"),
+            )));
+
+            assert_eq!(error.public_message(), "Database connection error");
+            assert!(error.to_string().contains("db.internal"));
         }
     }
 }
@@ -1,26 +1,87 @@
+use futures_util::TryStreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use sqlx::postgres::{PgPool, PgPoolOptions, PgRow, PgTypeInfo};
-use sqlx::{Column, Row, TypeInfo};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::types::{Oid, PgInterval, PgMoney};
+use sqlx::postgres::{
+    PgConnection, PgHasArrayType, PgPool, PgPoolOptions, PgRow, PgTypeInfo, PgTypeKind,
+    PgValueFormat, PgValueRef,
+};
+use sqlx::{Column, Executor, Row, TypeInfo, ValueRef};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
 use uuid::Uuid;
 
-use crate::config::DatabaseBackend;
+use crate::cancel::CancellableTransaction;
+use crate::config::{DatabaseBackend, SqlOperation};
+use crate::cursor::{CURSOR_NAME, Cursor, CursorPage, CursorRegistry, MAX_CURSORS_PER_DATABASE};
+use crate::params::{bind_typed, typed_param};
+use crate::parser::ParsedQuery;
+use crate::pools::{DEFAULT_MAX_CONNECTIONS, DedicatedPools};
+use crate::text_form;
 
 #[derive(Debug, Error)]
 pub enum ExecutorError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
 
+    /// Opening a connection to the backend failed (unreachable, authentication, TLS)
+    #[error("Connection error: {0}")]
+    Connection(sqlx::Error),
+
     #[error("Query timeout")]
     Timeout,
 
     #[error("Row limit exceeded (max: {0})")]
     RowLimitExceeded(u32),
+
+    #[error("Cursor not found: {0}")]
+    CursorNotFound(Uuid),
+
+    #[error("Too many open cursors (max: {0})")]
+    TooManyCursors(usize),
+
+    #[error("Too many concurrent queries (max: {0})")]
+    TooManyConcurrentQueries(u32),
+
+    #[error("Failed to decode column '{column}' of type {type_name}: {source}")]
+    Decode {
+        column: String,
+        type_name: String,
+        source: sqlx::Error,
+    },
+
+    #[error("Invalid parameter ${index}: {message}")]
+    InvalidParam { index: usize, message: String },
+
+    #[error("Query is too expensive: estimated cost {cost} (max: {max})")]
+    QueryTooExpensive { cost: f64, max: f64 },
+}
+
+impl ExecutorError {
+    /// True for postgate timeouts and statements canceled by Postgres
+    /// (statement_timeout, SQLSTATE 57014)
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            ExecutorError::Timeout => true,
+            ExecutorError::Database(e) => e
+                .as_database_error()
+                .and_then(|e| e.code())
+                .is_some_and(|code| code == "57014"),
+            _ => false,
+        }
+    }
+
+    /// Error raised by Postgres itself, with its SQLSTATE and constraint details
+    pub fn database_error(&self) -> Option<&sqlx::postgres::PgDatabaseError> {
+        match self {
+            ExecutorError::Database(e) => e.as_database_error()?.try_downcast_ref(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,14 +95,157 @@ pub struct QueryRequest {
 pub struct QueryResponse {
     pub rows: Vec<HashMap<String, JsonValue>>,
     pub row_count: usize,
+    /// Rows changed by a statement that returns none (DML without RETURNING, DDL).
+    /// Absent when the statement returns rows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows_affected: Option<u64>,
+    /// Only the first max_rows rows are returned (truncate mode)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// Result in columnar form: column metadata once, then each row as an array
+/// of values in column order (keeps duplicate column names)
+#[derive(Debug, Serialize)]
+pub struct ColumnarResponse {
+    pub columns: Vec<ColumnInfo>,
+    pub rows: Vec<Vec<JsonValue>>,
+    pub row_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows_affected: Option<u64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    /// Postgres type name (e.g. INT4, TEXT, TIMESTAMPTZ)
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// None when Postgres can't tell (e.g. computed expressions)
+    pub nullable: Option<bool>,
+}
+
+/// Raw result of a statement, converted by the caller into a response format
+#[derive(Debug)]
+pub struct QueryResult {
+    pub columns: Vec<ColumnInfo>,
+    pub rows: Vec<PgRow>,
+    /// Set for statements executed without fetching rows
+    /// (DML without RETURNING, DDL)
+    pub rows_affected: Option<u64>,
+    /// More than max_rows rows were produced and the rest was dropped
+    pub truncated: bool,
+}
+
+impl TryFrom<QueryResult> for QueryResponse {
+    type Error = ExecutorError;
+
+    fn try_from(result: QueryResult) -> Result<Self, ExecutorError> {
+        let row_count = result.rows.len();
+        let rows = result
+            .rows
+            .into_iter()
+            .map(row_to_json)
+            .collect::<Result<_, _>>()?;
+
+        Ok(QueryResponse {
+            rows,
+            row_count,
+            rows_affected: result.rows_affected,
+            truncated: result.truncated,
+        })
+    }
+}
+
+impl TryFrom<QueryResult> for ColumnarResponse {
+    type Error = ExecutorError;
+
+    fn try_from(result: QueryResult) -> Result<Self, ExecutorError> {
+        let row_count = result.rows.len();
+        let rows = result
+            .rows
+            .into_iter()
+            .map(row_to_values)
+            .collect::<Result<_, _>>()?;
+
+        Ok(ColumnarResponse {
+            columns: result.columns,
+            rows,
+            row_count,
+            rows_affected: result.rows_affected,
+            truncated: result.truncated,
+        })
+    }
+}
+
+/// Limits and preferences applied when executing a query
+#[derive(Debug, Clone, Copy)]
+pub struct ExecuteOptions {
+    pub max_rows: u32,
+    /// Enforced by postgate and inside Postgres (statement_timeout)
+    pub timeout: Duration,
+    /// Look up column types and nullability, even for empty results.
+    /// Costs an extra round-trip to the catalog.
+    pub describe_columns: bool,
+    /// Reject SELECT and DML whose estimated total cost (EXPLAIN) is higher
+    pub max_plan_cost: Option<f64>,
+    /// Return the first max_rows rows instead of failing with RowLimitExceeded
+    pub truncate: bool,
+    /// Transactions the database can run at the same time (None = no limit)
+    pub max_concurrent_queries: Option<u32>,
+    /// How long to wait for a free slot when the database is at its limit
+    pub queue_timeout: Duration,
+    /// Size of the pool of a dedicated backend (None = default)
+    pub max_connections: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionRequest {
+    pub queries: Vec<QueryRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionResponse {
+    pub results: Vec<QueryResponse>,
+}
+
+/// A validated statement to run as part of a transaction
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionStatement<'a> {
+    pub request: &'a QueryRequest,
+    pub operation: SqlOperation,
+    /// Fetch rows, or only count the rows affected
+    pub returns_rows: bool,
+}
+
+/// Item of a streaming query
+#[derive(Debug)]
+pub enum StreamItem {
+    Row(HashMap<String, JsonValue>),
+    /// max_rows was reached in truncate mode, no row follows
+    Truncated,
+}
+
+/// Rows produced by a streaming query, in order.
+/// An error is always the last item of the stream.
+pub type RowStream = mpsc::Receiver<Result<StreamItem, ExecutorError>>;
+
+/// Number of decoded rows buffered between the database and a slow client
+const STREAM_BUFFER_ROWS: usize = 64;
+
 /// Manages execution of queries across different database backends
 pub struct ExecutorPool {
     /// Shared pool for schema-based multi-tenancy
     shared_pool: PgPool,
-    /// Dedicated pools for premium users (lazy-loaded)
-    dedicated_pools: RwLock<HashMap<Uuid, Arc<PgPool>>>,
+    /// Dedicated pools for premium users (lazy-loaded, closed when idle)
+    dedicated_pools: Arc<DedicatedPools>,
+    /// Open server-side cursors
+    cursors: Arc<CursorRegistry>,
+    /// Concurrency slots of databases with max_concurrent_queries, with their limit.
+    /// Only databases with a slot in use or awaited are kept.
+    query_slots: std::sync::Mutex<HashMap<Uuid, (u32, Arc<Semaphore>)>>,
 }
 
 impl ExecutorPool {
@@ -52,8 +256,10 @@ impl ExecutorPool {
             .await?;
 
         Ok(Self {
+            dedicated_pools: DedicatedPools::start(shared_pool.clone()),
             shared_pool,
-            dedicated_pools: RwLock::new(HashMap::new()),
+            cursors: CursorRegistry::start(),
+            query_slots: std::sync::Mutex::new(HashMap::new()),
         })
     }
 
@@ -62,15 +268,33 @@ impl ExecutorPool {
         database_id: Uuid,
         backend: &DatabaseBackend,
         request: &QueryRequest,
-        max_rows: u32,
-        timeout_seconds: u64,
-        is_ddl: bool,
-    ) -> Result<QueryResponse, ExecutorError> {
-        let timeout = Duration::from_secs(timeout_seconds);
+        parsed: &ParsedQuery,
+        options: ExecuteOptions,
+    ) -> Result<QueryResult, ExecutorError> {
+        let result = tokio::time::timeout(
+            options.timeout,
+            self.execute_query(database_id, backend, request, parsed, options),
+        )
+        .await;
+
+        match result {
+            Ok(inner) => inner,
+            Err(_) => Err(ExecutorError::Timeout),
+        }
+    }
 
+    /// Execute several statements atomically: all of them are committed together,
+    /// or the whole transaction is rolled back on the first error.
+    pub async fn execute_transaction(
+        &self,
+        database_id: Uuid,
+        backend: &DatabaseBackend,
+        statements: &[TransactionStatement<'_>],
+        options: ExecuteOptions,
+    ) -> Result<TransactionResponse, ExecutorError> {
         let result = tokio::time::timeout(
-            timeout,
-            self.execute_query(database_id, backend, request, max_rows, is_ddl),
+            options.timeout,
+            self.execute_statements(database_id, backend, statements, options),
         )
         .await;
 
@@ -80,141 +304,349 @@ impl ExecutorPool {
         }
     }
 
-    async fn execute_query(
+    /// Execute a query and stream its rows as they arrive instead of buffering them.
+    /// The transaction is committed once every row has been sent, and rolled back if
+    /// the receiver is dropped early (e.g. the client disconnected) or a read-only
+    /// result is truncated.
+    pub async fn execute_stream(
         &self,
         database_id: Uuid,
         backend: &DatabaseBackend,
-        request: &QueryRequest,
-        max_rows: u32,
-        is_ddl: bool,
-    ) -> Result<QueryResponse, ExecutorError> {
-        match backend {
-            DatabaseBackend::Schema { schema_name } => {
-                self.execute_with_schema(schema_name, request, max_rows, is_ddl)
-                    .await
-            }
-            DatabaseBackend::Dedicated { connection_string } => {
-                self.execute_dedicated(database_id, connection_string, request, max_rows, is_ddl)
-                    .await
+        request: QueryRequest,
+        parsed: &ParsedQuery,
+        options: ExecuteOptions,
+    ) -> Result<RowStream, ExecutorError> {
+        let deadline = tokio::time::Instant::now() + options.timeout;
+        let operation = parsed.operation;
+        let read_only = parsed.is_read_only();
+        let mut tx = tokio::time::timeout_at(deadline, self.begin(database_id, backend, options))
+            .await
+            .map_err(|_| ExecutorError::Timeout)??;
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER_ROWS);
+
+        tokio::spawn(async move {
+            let stream = tx.run(async |conn| {
+                check_plan_cost(conn, &request, operation, options.max_plan_cost).await?;
+                stream_rows(conn, &request, read_only, options, &sender).await
+            });
+            let result = tokio::select! {
+                result = tokio::time::timeout_at(deadline, stream) => {
+                    result.unwrap_or(Err(ExecutorError::Timeout))
+                }
+                // The client may leave before the first row, e.g. while waiting on a lock
+                _ = sender.closed() => Ok(false),
+            };
+
+            let result = match result {
+                Ok(true) => tx.commit().await.map_err(ExecutorError::from),
+                // Receiver is gone or has all it gets: stop producing rows and roll back
+                Ok(false) => return tx.cancel(),
+                // Don't let Postgres send the rest of the result set
+                Err(e @ ExecutorError::RowLimitExceeded(_)) => {
+                    tx.cancel();
+                    Err(e)
+                }
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                let _ = sender.send(Err(e)).await;
             }
-        }
+        });
+
+        Ok(receiver)
     }
 
-    async fn execute_with_schema(
+    /// Declare a cursor for a SELECT and keep its transaction open for later fetches
+    pub async fn open_cursor(
         &self,
-        schema_name: &str,
+        database_id: Uuid,
+        backend: &DatabaseBackend,
         request: &QueryRequest,
-        max_rows: u32,
-        is_ddl: bool,
-    ) -> Result<QueryResponse, ExecutorError> {
-        // Use a transaction to set search_path, then execute the query
-        let safe_schema = schema_name.replace('"', "\"\"");
+        options: ExecuteOptions,
+    ) -> Result<Uuid, ExecutorError> {
+        let mut tx = self.begin(database_id, backend, options).await?;
 
-        let mut tx = self.shared_pool.begin().await?;
+        let sql = format!(
+            "DECLARE {} NO SCROLL CURSOR FOR {}",
+            CURSOR_NAME,
+            request.sql.trim().trim_end_matches(';')
+        );
+        let query = build_query(&sql, &request.params)?;
 
-        // Set the search_path for this transaction
-        sqlx::query(&format!("SET LOCAL search_path TO \"{}\"", safe_schema))
-            .execute(&mut *tx)
-            .await?;
+        let declare = tx.run(async |conn| {
+            check_plan_cost(conn, request, SqlOperation::Select, options.max_plan_cost).await?;
+            query.execute(conn).await?;
+            Ok::<_, ExecutorError>(())
+        });
+        tokio::time::timeout(options.timeout, declare)
+            .await
+            .map_err(|_| ExecutorError::Timeout)??;
 
-        // Execute the user query
-        let mut query = sqlx::query(&request.sql);
-        for (i, param) in request.params.iter().enumerate() {
-            query = bind_json_value(query, param, &request.sql, i + 1);
-        }
+        self.cursors
+            .insert(database_id, Cursor::new(tx))
+            .ok_or(ExecutorError::TooManyCursors(MAX_CURSORS_PER_DATABASE))
+    }
 
-        // DDL statements don't return rows, use execute() instead of fetch_all()
-        if is_ddl {
-            let result = query.execute(&mut *tx).await?;
-            tx.commit().await?;
+    /// Fetch the next rows of a cursor, at most `options.max_rows`, with the fetch
+    /// request's own statement_timeout.
+    /// The cursor is closed once exhausted, or on error; a FETCH cut off by the timeout
+    /// is canceled when the cursor is dropped.
+    pub async fn fetch_cursor(
+        &self,
+        database_id: Uuid,
+        cursor_id: Uuid,
+        count: u32,
+        options: ExecuteOptions,
+    ) -> Result<CursorPage, ExecutorError> {
+        let count = count.clamp(1, options.max_rows.max(1));
 
-            return Ok(QueryResponse {
-                rows: vec![],
-                row_count: result.rows_affected() as usize,
-            });
-        }
+        let cursor = self
+            .cursors
+            .get(database_id, cursor_id)
+            .ok_or(ExecutorError::CursorNotFound(cursor_id))?;
+        let mut cursor = cursor.lock().await;
 
-        let rows: Vec<PgRow> = query.fetch_all(&mut *tx).await?;
+        let statement_timeout = format!(
+            "SET LOCAL statement_timeout = {}",
+            options.timeout.as_millis()
+        );
+        let sql = format!("FETCH FORWARD {} FROM {}", count, CURSOR_NAME);
+        let fetch = cursor.tx.run(async |conn| {
+            sqlx::raw_sql(&statement_timeout)
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query(&sql).fetch_all(conn).await
+        });
+        let result = match tokio::time::timeout(options.timeout, fetch).await {
+            Ok(inner) => inner.map_err(ExecutorError::from),
+            Err(_) => Err(ExecutorError::Timeout),
+        };
 
-        // Commit the transaction
-        tx.commit().await?;
+        let rows = match result {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.cursors.remove(database_id, cursor_id);
+                return Err(e);
+            }
+        };
 
-        if rows.len() > max_rows as usize {
-            return Err(ExecutorError::RowLimitExceeded(max_rows));
+        cursor.touch();
+
+        // Read-only, so dropping the transaction (rollback) is as good as a commit
+        let done = rows.len() < count as usize;
+        if done {
+            self.cursors.remove(database_id, cursor_id);
         }
 
-        let row_count = rows.len();
-        let rows = rows.into_iter().map(row_to_json).collect();
+        // A page that can't be decoded is lost, so the cursor is closed like on any error
+        let rows = match rows
+            .into_iter()
+            .map(row_to_json)
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.cursors.remove(database_id, cursor_id);
+                return Err(e);
+            }
+        };
+
+        Ok(CursorPage {
+            row_count: rows.len(),
+            rows,
+            done,
+        })
+    }
 
-        Ok(QueryResponse { rows, row_count })
+    /// Close a cursor and release its connection
+    pub fn close_cursor(&self, database_id: Uuid, cursor_id: Uuid) -> Result<(), ExecutorError> {
+        self.cursors
+            .remove(database_id, cursor_id)
+            .map(drop)
+            .ok_or(ExecutorError::CursorNotFound(cursor_id))
     }
 
-    async fn execute_dedicated(
+    async fn execute_query(
         &self,
         database_id: Uuid,
-        connection_string: &str,
+        backend: &DatabaseBackend,
         request: &QueryRequest,
-        max_rows: u32,
-        is_ddl: bool,
-    ) -> Result<QueryResponse, ExecutorError> {
-        let pool = self
-            .get_or_create_dedicated_pool(database_id, connection_string)
-            .await?;
+        parsed: &ParsedQuery,
+        options: ExecuteOptions,
+    ) -> Result<QueryResult, ExecutorError> {
+        let mut tx = self.begin(database_id, backend, options).await?;
 
-        let mut query = sqlx::query(&request.sql);
-        for (i, param) in request.params.iter().enumerate() {
-            query = bind_json_value(query, param, &request.sql, i + 1);
-        }
+        let result = tx
+            .run(async |conn| {
+                run_statement(
+                    conn,
+                    request,
+                    parsed.operation,
+                    parsed.returns_rows,
+                    options,
+                )
+                .await
+            })
+            .await;
 
-        // DDL statements don't return rows
-        if is_ddl {
-            let result = query.execute(pool.as_ref()).await?;
-            return Ok(QueryResponse {
-                rows: vec![],
-                row_count: result.rows_affected() as usize,
-            });
-        }
+        let result = match result {
+            // Don't let Postgres send the rest of the result set; nothing to commit
+            Ok(result) if result.truncated && parsed.is_read_only() => {
+                tx.cancel();
+                return Ok(result);
+            }
+            Ok(result) => result,
+            // Don't let Postgres send the rest of the result set
+            Err(e @ ExecutorError::RowLimitExceeded(_)) => {
+                tx.cancel();
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
 
-        let rows: Vec<PgRow> = query.fetch_all(pool.as_ref()).await?;
+        // A truncated DML result set is read to the end by the commit, without being kept
+        tx.commit().await?;
 
-        if rows.len() > max_rows as usize {
-            return Err(ExecutorError::RowLimitExceeded(max_rows));
-        }
+        Ok(result)
+    }
 
-        let row_count = rows.len();
-        let rows = rows.into_iter().map(row_to_json).collect();
+    async fn execute_statements(
+        &self,
+        database_id: Uuid,
+        backend: &DatabaseBackend,
+        statements: &[TransactionStatement<'_>],
+        options: ExecuteOptions,
+    ) -> Result<TransactionResponse, ExecutorError> {
+        let mut tx = self.begin(database_id, backend, options).await?;
+
+        // Any error drops the transaction, which rolls it back
+        let results = tx
+            .run(async |conn| {
+                let mut results = Vec::with_capacity(statements.len());
+                for statement in statements {
+                    let result = run_statement(
+                        conn,
+                        statement.request,
+                        statement.operation,
+                        statement.returns_rows,
+                        options,
+                    )
+                    .await?;
+                    results.push(result.try_into()?);
+                }
+                Ok::<_, ExecutorError>(results)
+            })
+            .await?;
+
+        tx.commit().await?;
 
-        Ok(QueryResponse { rows, row_count })
+        Ok(TransactionResponse { results })
     }
 
-    async fn get_or_create_dedicated_pool(
+    /// Open a transaction on the backend of a database, with `statement_timeout` set.
+    /// For schema backends, the search_path is scoped to the tenant schema.
+    /// Waits for a concurrency slot first when the database has a limit.
+    async fn begin(
         &self,
         database_id: Uuid,
-        connection_string: &str,
-    ) -> Result<Arc<PgPool>, ExecutorError> {
-        // Check if pool exists
-        {
-            let pools = self.dedicated_pools.read().await;
-            if let Some(pool) = pools.get(&database_id) {
-                return Ok(pool.clone());
+        backend: &DatabaseBackend,
+        options: ExecuteOptions,
+    ) -> Result<CancellableTransaction, ExecutorError> {
+        let slot = self.acquire_slot(database_id, options).await?;
+
+        let statement_timeout = format!(
+            "SET LOCAL statement_timeout = {}",
+            options.timeout.as_millis()
+        );
+
+        // The backend PID is used to cancel statements postgate stops waiting for
+        let backend_pid = "SELECT pg_backend_pid()";
+
+        match backend {
+            DatabaseBackend::Schema { schema_name } => {
+                let safe_schema = schema_name.replace('"', "\"\"");
+
+                let mut tx = self
+                    .shared_pool
+                    .begin()
+                    .await
+                    .map_err(ExecutorError::Connection)?;
+
+                // Set the search_path for this transaction
+                let pid: i32 = sqlx::raw_sql(&format!(
+                    "SET LOCAL search_path TO \"{}\"; {}; {}",
+                    safe_schema, statement_timeout, backend_pid
+                ))
+                .fetch_one(&mut *tx)
+                .await?
+                .try_get(0)?;
+
+                Ok(CancellableTransaction::new(
+                    tx,
+                    pid,
+                    &self.shared_pool,
+                    slot,
+                ))
+            }
+            DatabaseBackend::Dedicated { connection_string } => {
+                let max_connections = options.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+                let pool = self
+                    .dedicated_pools
+                    .get_or_create(database_id, connection_string, max_connections)
+                    .await
+                    .map_err(ExecutorError::Connection)?;
+
+                // Connections are opened lazily, so logging in can also fail here
+                let mut tx = pool.begin().await.map_err(ExecutorError::Connection)?;
+
+                let pid: i32 = sqlx::raw_sql(&format!("{}; {}", statement_timeout, backend_pid))
+                    .fetch_one(&mut *tx)
+                    .await?
+                    .try_get(0)?;
+
+                Ok(CancellableTransaction::new(tx, pid, &pool, slot))
             }
         }
+    }
 
-        // Create new pool
-        let pool = Arc::new(
-            PgPoolOptions::new()
-                .max_connections(10)
-                .connect(connection_string)
-                .await?,
-        );
+    /// Wait for one of the database's concurrency slots, for at most `options.queue_timeout`.
+    /// The slot is held until the permit is dropped.
+    async fn acquire_slot(
+        &self,
+        database_id: Uuid,
+        options: ExecuteOptions,
+    ) -> Result<Option<OwnedSemaphorePermit>, ExecutorError> {
+        let Some(limit) = options.max_concurrent_queries else {
+            return Ok(None);
+        };
 
-        // Store pool
-        {
-            let mut pools = self.dedicated_pools.write().await;
-            pools.insert(database_id, pool.clone());
+        let semaphore = {
+            let mut slots = self.query_slots.lock().expect("query slots poisoned");
+
+            // Semaphores nobody holds a permit of or waits on are dropped, so deleted
+            // and inactive databases don't pile up
+            slots.retain(|_, (_, semaphore)| Arc::strong_count(semaphore) > 1);
+
+            let (current, semaphore) = slots
+                .entry(database_id)
+                .or_insert_with(|| (limit, Arc::new(Semaphore::new(limit as usize))));
+            if *current != limit {
+                resize_slots(semaphore, *current, limit);
+                *current = limit;
+            }
+            semaphore.clone()
+        };
+
+        match tokio::time::timeout(options.queue_timeout, semaphore.acquire_owned()).await {
+            Ok(permit) => Ok(Some(permit.expect("query slots are never closed"))),
+            Err(_) => Err(ExecutorError::TooManyConcurrentQueries(limit)),
         }
+    }
 
-        Ok(pool)
+    /// Close the dedicated pool of a database, if one is open (e.g. once it is deleted)
+    pub fn remove_dedicated_pool(&self, database_id: Uuid) {
+        self.dedicated_pools.remove(database_id);
     }
 
     /// Get the shared pool (for store operations)
@@ -223,6 +655,196 @@ impl ExecutorPool {
     }
 }
 
+/// Change the number of slots of a database while some may be in use.
+/// Slots over the new limit are taken back as soon as they are released.
+fn resize_slots(semaphore: &Arc<Semaphore>, from: u32, to: u32) {
+    if to >= from {
+        semaphore.add_permits((to - from) as usize);
+        return;
+    }
+
+    let excess = (from - to) as usize;
+    let missing = excess - semaphore.forget_permits(excess);
+    if missing > 0 {
+        let semaphore = semaphore.clone();
+        tokio::spawn(async move {
+            if let Ok(permits) = semaphore.acquire_many_owned(missing as u32).await {
+                permits.forget();
+            }
+        });
+    }
+}
+
+/// Run a single statement on an open connection (usually a transaction)
+async fn run_statement(
+    conn: &mut PgConnection,
+    request: &QueryRequest,
+    operation: SqlOperation,
+    returns_rows: bool,
+    options: ExecuteOptions,
+) -> Result<QueryResult, ExecutorError> {
+    check_plan_cost(conn, request, operation, options.max_plan_cost).await?;
+
+    let query = build_query(&request.sql, &request.params)?;
+
+    // Statements without rows (DDL, DML without RETURNING) report the rows they affected
+    if !returns_rows {
+        let result = query.execute(&mut *conn).await?;
+
+        return Ok(QueryResult {
+            columns: vec![],
+            rows: vec![],
+            rows_affected: Some(result.rows_affected()),
+            truncated: false,
+        });
+    }
+
+    // Described first: once a result set is truncated, the next use of the connection
+    // would read the rest of it
+    let described = if options.describe_columns {
+        Some(describe_columns(conn, &request.sql).await?)
+    } else {
+        None
+    };
+
+    // Rows are counted as they arrive, so an oversized result set is never held in memory
+    let mut rows: Vec<PgRow> = Vec::new();
+    let mut truncated = false;
+    let mut stream = query.fetch(&mut *conn);
+
+    while let Some(row) = stream.try_next().await? {
+        if rows.len() == options.max_rows as usize {
+            if !options.truncate {
+                return Err(ExecutorError::RowLimitExceeded(options.max_rows));
+            }
+            truncated = true;
+            break;
+        }
+        rows.push(row);
+    }
+
+    drop(stream);
+
+    let columns = described.unwrap_or_else(|| rows.first().map(columns_of).unwrap_or_default());
+
+    Ok(QueryResult {
+        columns,
+        rows,
+        rows_affected: None,
+        truncated,
+    })
+}
+
+/// Reject the statement if the planner's estimated total cost is above `max_cost`.
+/// Nothing is run when no ceiling is set, or for statements without a plan (DDL).
+async fn check_plan_cost(
+    conn: &mut PgConnection,
+    request: &QueryRequest,
+    operation: SqlOperation,
+    max_cost: Option<f64>,
+) -> Result<(), ExecutorError> {
+    let Some(max) = max_cost.filter(|_| operation.is_explainable()) else {
+        return Ok(());
+    };
+
+    let sql = format!(
+        "EXPLAIN (FORMAT JSON) {}",
+        request.sql.trim().trim_end_matches(';')
+    );
+    let query = build_query(&sql, &request.params)?;
+
+    let row = query.fetch_one(&mut *conn).await?;
+    let plan: JsonValue = row.try_get(0)?;
+
+    // A guard: a plan of unexpected shape fails the query rather than passing as free
+    let cost = plan_total_cost(&plan).ok_or_else(|| {
+        decode_error(
+            &row,
+            0,
+            sqlx::Error::Decode("plan has no \"Total Cost\"".into()),
+        )
+    })?;
+
+    if cost > max {
+        return Err(ExecutorError::QueryTooExpensive { cost, max });
+    }
+
+    Ok(())
+}
+
+/// Estimated total cost of an `EXPLAIN (FORMAT JSON)` plan
+fn plan_total_cost(plan: &JsonValue) -> Option<f64> {
+    plan.get(0)?.get("Plan")?.get("Total Cost")?.as_f64()
+}
+
+/// Column metadata from the prepared statement, including nullability
+async fn describe_columns(
+    conn: &mut PgConnection,
+    sql: &str,
+) -> Result<Vec<ColumnInfo>, ExecutorError> {
+    let describe = conn.describe(sql).await?;
+
+    Ok(describe
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(i, column)| ColumnInfo {
+            name: column.name().to_string(),
+            type_name: column.type_info().name().to_string(),
+            nullable: describe.nullable(i),
+        })
+        .collect())
+}
+
+/// Column metadata available from a fetched row (nullability unknown)
+fn columns_of(row: &PgRow) -> Vec<ColumnInfo> {
+    row.columns()
+        .iter()
+        .map(|column| ColumnInfo {
+            name: column.name().to_string(),
+            type_name: column.type_info().name().to_string(),
+            nullable: None,
+        })
+        .collect()
+}
+
+/// Send rows to `sender` one by one as they are fetched.
+/// Returns false if the rest of the result set is to be dropped: the receiver is gone,
+/// or a read-only result was truncated.
+async fn stream_rows(
+    conn: &mut PgConnection,
+    request: &QueryRequest,
+    read_only: bool,
+    options: ExecuteOptions,
+    sender: &mpsc::Sender<Result<StreamItem, ExecutorError>>,
+) -> Result<bool, ExecutorError> {
+    let query = build_query(&request.sql, &request.params)?;
+
+    let mut rows = query.fetch(&mut *conn);
+    let mut row_count: usize = 0;
+
+    while let Some(row) = rows.try_next().await? {
+        row_count += 1;
+        if row_count > options.max_rows as usize {
+            if !options.truncate {
+                return Err(ExecutorError::RowLimitExceeded(options.max_rows));
+            }
+            let sent = sender.send(Ok(StreamItem::Truncated)).await.is_ok();
+            return Ok(sent && !read_only);
+        }
+
+        if sender
+            .send(Ok(StreamItem::Row(row_to_json(row)?)))
+            .await
+            .is_err()
+        {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
 /// Returns true if `$param_idx` appears in the SQL with an explicit cast (`::type`).
 /// Skips occurrences where the next char is a digit (so `$1` doesn't match inside `$11`).
 /// String/comment-aware analysis would be more robust but a false positive only means we
@@ -260,6 +882,28 @@ pub fn has_explicit_cast(sql: &str, param_idx: usize) -> bool {
     false
 }
 
+/// Query with its parameters bound, typed ones with their declared type
+fn build_query<'q>(
+    sql: &'q str,
+    params: &'q [JsonValue],
+) -> Result<sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments>, ExecutorError> {
+    let mut query = sqlx::query(sql);
+
+    for (i, param) in params.iter().enumerate() {
+        query = match typed_param(param) {
+            Some(typed) => {
+                bind_typed(query, typed).map_err(|message| ExecutorError::InvalidParam {
+                    index: i + 1,
+                    message,
+                })?
+            }
+            None => bind_json_value(query, param, sql, i + 1),
+        };
+    }
+
+    Ok(query)
+}
+
 fn bind_json_value<'q>(
     query: sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments>,
     value: &'q JsonValue,
@@ -304,116 +948,494 @@ fn bind_json_value<'q>(
     }
 }
 
-fn row_to_json(row: PgRow) -> HashMap<String, JsonValue> {
+fn row_to_json(row: PgRow) -> Result<HashMap<String, JsonValue>, ExecutorError> {
     let mut map = HashMap::new();
 
     for column in row.columns() {
         let name = column.name().to_string();
-        let value = get_column_value(&row, column.ordinal(), column.type_info());
+        let value = get_column_value(&row, column.ordinal(), column.type_info())?;
         map.insert(name, value);
     }
 
-    map
+    Ok(map)
 }
 
-fn get_column_value(row: &PgRow, idx: usize, type_info: &PgTypeInfo) -> JsonValue {
-    let type_name = type_info.name();
+pub(crate) fn row_to_values(row: PgRow) -> Result<Vec<JsonValue>, ExecutorError> {
+    row.columns()
+        .iter()
+        .map(|column| get_column_value(&row, column.ordinal(), column.type_info()))
+        .collect()
+}
 
-    match type_name {
-        "BOOL" => row
-            .try_get::<Option<bool>, _>(idx)
-            .ok()
-            .flatten()
-            .map(JsonValue::Bool)
-            .unwrap_or(JsonValue::Null),
-        "INT2" => row
-            .try_get::<Option<i16>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::Number(v.into()))
-            .unwrap_or(JsonValue::Null),
-        "INT4" => row
-            .try_get::<Option<i32>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::Number(v.into()))
-            .unwrap_or(JsonValue::Null),
-        "INT8" => row
-            .try_get::<Option<i64>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::Number(v.into()))
-            .unwrap_or(JsonValue::Null),
-        "FLOAT4" => row
-            .try_get::<Option<f32>, _>(idx)
-            .ok()
-            .flatten()
-            .and_then(|v| serde_json::Number::from_f64(v as f64))
-            .map(JsonValue::Number)
-            .unwrap_or(JsonValue::Null),
-        "FLOAT8" => row
-            .try_get::<Option<f64>, _>(idx)
-            .ok()
-            .flatten()
-            .and_then(serde_json::Number::from_f64)
-            .map(JsonValue::Number)
-            .unwrap_or(JsonValue::Null),
-        "TEXT" | "VARCHAR" | "CHAR" | "NAME" | "BPCHAR" => row
-            .try_get::<Option<String>, _>(idx)
-            .ok()
-            .flatten()
-            .map(JsonValue::String)
-            .unwrap_or(JsonValue::Null),
-        "UUID" => row
-            .try_get::<Option<uuid::Uuid>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::String(v.to_string()))
-            .unwrap_or(JsonValue::Null),
-        "TIMESTAMPTZ" => row
-            .try_get::<Option<chrono::DateTime<chrono::Utc>>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::String(v.to_rfc3339()))
-            .unwrap_or(JsonValue::Null),
-        "TIMESTAMP" => row
-            .try_get::<Option<chrono::NaiveDateTime>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::String(v.to_string()))
-            .unwrap_or(JsonValue::Null),
-        "DATE" => row
-            .try_get::<Option<chrono::NaiveDate>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::String(v.to_string()))
-            .unwrap_or(JsonValue::Null),
-        "TIME" => row
-            .try_get::<Option<chrono::NaiveTime>, _>(idx)
-            .ok()
-            .flatten()
-            .map(|v| JsonValue::String(v.to_string()))
-            .unwrap_or(JsonValue::Null),
-        "JSON" | "JSONB" => row
-            .try_get::<Option<JsonValue>, _>(idx)
+/// Decode a column as JSON. NULL is `null`; a value that can't be decoded is an error
+/// naming the column rather than a silent `null`.
+pub(crate) fn get_column_value(
+    row: &PgRow,
+    idx: usize,
+    type_info: &PgTypeInfo,
+) -> Result<JsonValue, ExecutorError> {
+    decode_column(row, idx, type_info).map_err(|source| decode_error(row, idx, source))
+}
+
+/// Decode failure naming the column and its type
+pub(crate) fn decode_error(row: &PgRow, idx: usize, source: sqlx::Error) -> ExecutorError {
+    let column = row.column(idx);
+
+    ExecutorError::Decode {
+        column: column.name().to_string(),
+        type_name: column.type_info().name().to_string(),
+        source,
+    }
+}
+
+fn decode_column(
+    row: &PgRow,
+    idx: usize,
+    type_info: &PgTypeInfo,
+) -> Result<JsonValue, sqlx::Error> {
+    match type_info.kind() {
+        PgTypeKind::Array(element) => return decode_array(row, idx, element),
+        // Enums are sent as their label
+        PgTypeKind::Enum(_) => {
+            return Ok(to_json(
+                row.try_get_unchecked::<Option<String>, _>(idx)?,
+                JsonValue::String,
+            ));
+        }
+        _ => {}
+    }
+
+    match type_info.name() {
+        "BOOL" => scalar::<bool>(row, idx, JsonValue::Bool),
+        "INT2" => scalar::<i16>(row, idx, |v| v.into()),
+        "INT4" => scalar::<i32>(row, idx, |v| v.into()),
+        "INT8" => scalar::<i64>(row, idx, |v| v.into()),
+        "OID" => scalar::<Oid>(row, idx, |v| v.0.into()),
+        "FLOAT4" => scalar::<f32>(row, idx, |v| float_to_json(v as f64)),
+        "FLOAT8" => scalar::<f64>(row, idx, float_to_json),
+        "NUMERIC" => scalar::<Numeric>(row, idx, |v| JsonValue::String(v.0)),
+        "MONEY" => scalar::<PgMoney>(row, idx, money_to_json),
+        "TEXT" | "VARCHAR" | "CHAR" | "NAME" | "BPCHAR" => {
+            scalar::<String>(row, idx, JsonValue::String)
+        }
+        "\"CHAR\"" => scalar::<i8>(row, idx, |v| {
+            JsonValue::String((v as u8 as char).to_string())
+        }),
+        "UUID" => scalar::<uuid::Uuid>(row, idx, |v| JsonValue::String(v.to_string())),
+        "BYTEA" => scalar::<Vec<u8>>(row, idx, bytea_to_json),
+        "TIMESTAMPTZ" => {
+            scalar::<chrono::DateTime<chrono::Utc>>(row, idx, |v| JsonValue::String(v.to_rfc3339()))
+        }
+        "TIMESTAMP" => scalar::<chrono::NaiveDateTime>(row, idx, display_to_json),
+        "DATE" => scalar::<chrono::NaiveDate>(row, idx, display_to_json),
+        "TIME" => scalar::<chrono::NaiveTime>(row, idx, display_to_json),
+        "INTERVAL" => scalar::<PgInterval>(row, idx, |v| JsonValue::String(interval_to_string(&v))),
+        "INET" | "CIDR" => scalar::<Inet>(row, idx, |v| JsonValue::String(v.0)),
+        "MACADDR" | "MACADDR8" => scalar::<MacAddr>(row, idx, |v| JsonValue::String(v.0)),
+        "JSON" | "JSONB" => scalar::<JsonValue>(row, idx, |v| v),
+        "VOID" => Ok(JsonValue::Null),
+        // Other types (geometric, tsvector, ranges, extension types...) in their text form
+        _ => text_form_scalar(row, idx, type_info),
+    }
+}
+
+/// Postgres arrays as JSON arrays, NULL elements included
+fn decode_array(row: &PgRow, idx: usize, element: &PgTypeInfo) -> Result<JsonValue, sqlx::Error> {
+    if let PgTypeKind::Enum(_) = element.kind() {
+        let values = row.try_get_unchecked::<Option<Vec<Option<String>>>, _>(idx)?;
+        return Ok(to_json(values, |values| {
+            values
+                .into_iter()
+                .map(|v| to_json(v, JsonValue::String))
+                .collect()
+        }));
+    }
+
+    match element.name() {
+        "BOOL" => array::<bool>(row, idx, JsonValue::Bool),
+        "INT2" => array::<i16>(row, idx, |v| v.into()),
+        "INT4" => array::<i32>(row, idx, |v| v.into()),
+        "INT8" => array::<i64>(row, idx, |v| v.into()),
+        "FLOAT4" => array::<f32>(row, idx, |v| float_to_json(v as f64)),
+        "FLOAT8" => array::<f64>(row, idx, float_to_json),
+        "NUMERIC" => array::<Numeric>(row, idx, |v| JsonValue::String(v.0)),
+        "MONEY" => array::<PgMoney>(row, idx, money_to_json),
+        "UUID" => array::<uuid::Uuid>(row, idx, |v| JsonValue::String(v.to_string())),
+        "BYTEA" => array::<Vec<u8>>(row, idx, bytea_to_json),
+        "TIMESTAMPTZ" => {
+            array::<chrono::DateTime<chrono::Utc>>(row, idx, |v| JsonValue::String(v.to_rfc3339()))
+        }
+        "TIMESTAMP" => array::<chrono::NaiveDateTime>(row, idx, display_to_json),
+        "DATE" => array::<chrono::NaiveDate>(row, idx, display_to_json),
+        "TIME" => array::<chrono::NaiveTime>(row, idx, display_to_json),
+        "INTERVAL" => array::<PgInterval>(row, idx, |v| JsonValue::String(interval_to_string(&v))),
+        "INET" | "CIDR" => array::<Inet>(row, idx, |v| JsonValue::String(v.0)),
+        "MACADDR" | "MACADDR8" => array::<MacAddr>(row, idx, |v| JsonValue::String(v.0)),
+        "JSON" | "JSONB" => array::<JsonValue>(row, idx, |v| v),
+        "TEXT" | "VARCHAR" | "CHAR" | "NAME" | "BPCHAR" => {
+            array::<String>(row, idx, JsonValue::String)
+        }
+        _ => text_form_array(row, idx, element),
+    }
+}
+
+/// Value in its Postgres text form, see [`text_form`]
+fn text_form_scalar(
+    row: &PgRow,
+    idx: usize,
+    type_info: &PgTypeInfo,
+) -> Result<JsonValue, sqlx::Error> {
+    let value = row.try_get_raw(idx)?;
+    if value.is_null() {
+        return Ok(JsonValue::Null);
+    }
+
+    let text = match value.format() {
+        PgValueFormat::Text => value.as_str().map(str::to_string),
+        PgValueFormat::Binary => value
+            .as_bytes()
+            .and_then(|bytes| text_form::text_form(type_info, bytes)),
+    };
+
+    text.map(JsonValue::String).map_err(sqlx::Error::Decode)
+}
+
+/// Array elements in their Postgres text form, see [`text_form`]
+fn text_form_array(
+    row: &PgRow,
+    idx: usize,
+    element: &PgTypeInfo,
+) -> Result<JsonValue, sqlx::Error> {
+    let value = row.try_get_raw(idx)?;
+    if value.is_null() {
+        return Ok(JsonValue::Null);
+    }
+
+    match value.format() {
+        PgValueFormat::Text => Ok(JsonValue::String(
+            value.as_str().map_err(sqlx::Error::Decode)?.to_string(),
+        )),
+        PgValueFormat::Binary => value
+            .as_bytes()
+            .and_then(|bytes| text_form::array_text_forms(element, bytes))
+            .map_err(sqlx::Error::Decode),
+    }
+}
+
+fn scalar<'r, T>(
+    row: &'r PgRow,
+    idx: usize,
+    convert: impl Fn(T) -> JsonValue,
+) -> Result<JsonValue, sqlx::Error>
+where
+    T: sqlx::Decode<'r, sqlx::Postgres> + sqlx::Type<sqlx::Postgres>,
+{
+    Ok(to_json(row.try_get::<Option<T>, _>(idx)?, convert))
+}
+
+fn array<'r, T>(
+    row: &'r PgRow,
+    idx: usize,
+    convert: impl Fn(T) -> JsonValue,
+) -> Result<JsonValue, sqlx::Error>
+where
+    Vec<Option<T>>: sqlx::Decode<'r, sqlx::Postgres> + sqlx::Type<sqlx::Postgres>,
+{
+    let values = row.try_get::<Option<Vec<Option<T>>>, _>(idx)?;
+
+    Ok(to_json(values, |values| {
+        values.into_iter().map(|v| to_json(v, &convert)).collect()
+    }))
+}
+
+fn to_json<T>(value: Option<T>, convert: impl FnOnce(T) -> JsonValue) -> JsonValue {
+    value.map(convert).unwrap_or(JsonValue::Null)
+}
+
+fn display_to_json(value: impl std::fmt::Display) -> JsonValue {
+    JsonValue::String(value.to_string())
+}
+
+/// JSON has no NaN or infinities, they are sent with their Postgres spelling
+fn float_to_json(value: f64) -> JsonValue {
+    match serde_json::Number::from_f64(value) {
+        Some(number) => JsonValue::Number(number),
+        None if value.is_nan() => JsonValue::String("NaN".to_string()),
+        None if value > 0.0 => JsonValue::String("Infinity".to_string()),
+        None => JsonValue::String("-Infinity".to_string()),
+    }
+}
+
+/// MONEY as an exact decimal string in major units (`-12.34`).
+/// The binary value counts minor units, whose number of digits comes from the server's
+/// lc_monetary; two are assumed, like most locales (not `ja_JP`, whose yen has none).
+/// Servers with such a locale should cast MONEY to NUMERIC in their queries.
+fn money_to_json(value: PgMoney) -> JsonValue {
+    let cents = value.0.unsigned_abs();
+    let sign = if value.0 < 0 { "-" } else { "" };
+
+    JsonValue::String(format!("{}{}.{:02}", sign, cents / 100, cents % 100))
+}
+
+fn bytea_to_json(value: Vec<u8>) -> JsonValue {
+    use base64::Engine;
+
+    JsonValue::String(base64::engine::general_purpose::STANDARD.encode(value))
+}
+
+/// Interval in the Postgres output style: `1 year 2 mons -3 days +04:05:06.5`
+fn interval_to_string(interval: &PgInterval) -> String {
+    let mut parts = Vec::new();
+    // A positive part after a negative one gets an explicit `+`, like Postgres does
+    let mut after_negative = false;
+
+    for (value, unit) in [
+        (interval.months as i64 / 12, "year"),
+        (interval.months as i64 % 12, "mon"),
+        (interval.days as i64, "day"),
+    ] {
+        if value == 0 {
+            continue;
+        }
+
+        let plus = if after_negative && value > 0 { "+" } else { "" };
+        let plural = if value != 1 { "s" } else { "" };
+        parts.push(format!("{plus}{value} {unit}{plural}"));
+        after_negative = value < 0;
+    }
+
+    if interval.microseconds != 0 || parts.is_empty() {
+        let micros = interval.microseconds.unsigned_abs();
+        let sign = if interval.microseconds < 0 {
+            "-"
+        } else if after_negative {
+            "+"
+        } else {
+            ""
+        };
+
+        let mut time = format!(
+            "{sign}{:02}:{:02}:{:02}",
+            micros / 3_600_000_000,
+            micros / 60_000_000 % 60,
+            micros / 1_000_000 % 60
+        );
+        let fraction = micros % 1_000_000;
+        if fraction != 0 {
+            time.push_str(format!(".{fraction:06}").trim_end_matches('0'));
+        }
+        parts.push(time);
+    }
+
+    parts.join(" ")
+}
+
+/// NUMERIC decoded as an exact decimal string, keeping the display scale
+/// (`12.50` stays `12.50`)
+struct Numeric(String);
+
+impl sqlx::Type<sqlx::Postgres> for Numeric {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("numeric")
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        ty.name() == "NUMERIC"
+    }
+}
+
+impl PgHasArrayType for Numeric {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_numeric")
+    }
+
+    fn array_compatible(ty: &PgTypeInfo) -> bool {
+        ty.name() == "NUMERIC[]"
+    }
+}
+
+impl<'r> sqlx::Decode<'r, sqlx::Postgres> for Numeric {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        match value.format() {
+            PgValueFormat::Text => Ok(Numeric(value.as_str()?.to_string())),
+            PgValueFormat::Binary => numeric_binary_to_string(value.as_bytes()?)
+                .map(Numeric)
+                .ok_or_else(|| "invalid binary NUMERIC".into()),
+        }
+    }
+}
+
+/// INET and CIDR in their Postgres text form (`10.0.0.1`, `10.0.0.0/8`)
+struct Inet(String);
+
+impl sqlx::Type<sqlx::Postgres> for Inet {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("inet")
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        matches!(ty.name(), "INET" | "CIDR")
+    }
+}
+
+impl PgHasArrayType for Inet {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_inet")
+    }
+
+    fn array_compatible(ty: &PgTypeInfo) -> bool {
+        matches!(ty.name(), "INET[]" | "CIDR[]")
+    }
+}
+
+impl<'r> sqlx::Decode<'r, sqlx::Postgres> for Inet {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        if value.format() == PgValueFormat::Text {
+            return Ok(Inet(value.as_str()?.to_string()));
+        }
+
+        // family, bits, is_cidr, address length, then the address bytes
+        let bytes = value.as_bytes()?;
+        let (&[family, bits, is_cidr, len], address) = bytes
+            .split_first_chunk::<4>()
+            .ok_or("invalid binary INET")?;
+
+        let (address, max_bits): (std::net::IpAddr, u8) = match (family, len, address) {
+            (2, 4, address) => (<[u8; 4]>::try_from(address)?.into(), 32),
+            (3, 16, address) => (<[u8; 16]>::try_from(address)?.into(), 128),
+            _ => return Err("invalid binary INET".into()),
+        };
+
+        if is_cidr == 0 && bits == max_bits {
+            Ok(Inet(address.to_string()))
+        } else {
+            Ok(Inet(format!("{}/{}", address, bits)))
+        }
+    }
+}
+
+/// MACADDR and MACADDR8 as colon-separated hex (`08:00:2b:01:02:03`)
+struct MacAddr(String);
+
+impl sqlx::Type<sqlx::Postgres> for MacAddr {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("macaddr")
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        matches!(ty.name(), "MACADDR" | "MACADDR8")
+    }
+}
+
+impl PgHasArrayType for MacAddr {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_macaddr")
+    }
+
+    fn array_compatible(ty: &PgTypeInfo) -> bool {
+        matches!(ty.name(), "MACADDR[]" | "MACADDR8[]")
+    }
+}
+
+impl<'r> sqlx::Decode<'r, sqlx::Postgres> for MacAddr {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        match value.format() {
+            PgValueFormat::Text => Ok(MacAddr(value.as_str()?.to_string())),
+            PgValueFormat::Binary => Ok(MacAddr(
+                value
+                    .as_bytes()?
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(":"),
+            )),
+        }
+    }
+}
+
+/// Decode a NUMERIC column as an exact decimal string, keeping the display scale
+/// (`12.50` stays `12.50`). Returns None for NULL.
+#[cfg(feature = "arrow")]
+pub(crate) fn decode_numeric(row: &PgRow, idx: usize) -> Result<Option<String>, ExecutorError> {
+    row.try_get::<Option<Numeric>, _>(idx)
+        .map(|v| v.map(|v| v.0))
+        .map_err(|source| decode_error(row, idx, source))
+}
+
+/// Binary NUMERIC layout: ndigits, weight, sign, dscale (all 16-bit),
+/// then `ndigits` base-10000 digits, the first one having the given weight.
+pub(crate) fn numeric_binary_to_string(bytes: &[u8]) -> Option<String> {
+    let header = |i: usize| Some(i16::from_be_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]));
+
+    let ndigits = header(0)? as usize;
+    let weight = header(2)? as i64;
+    let sign = header(4)? as u16;
+    let dscale = header(6)? as usize;
+
+    match sign {
+        0xC000 => return Some("NaN".to_string()),
+        0xD000 => return Some("Infinity".to_string()),
+        0xF000 => return Some("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let digits = (0..ndigits)
+        .map(|i| header(8 + i * 2))
+        .collect::<Option<Vec<i16>>>()?;
+    let digit = |k: i64| {
+        usize::try_from(k)
             .ok()
-            .flatten()
-            .unwrap_or(JsonValue::Null),
-        _ => {
-            // Fallback: try as string
-            row.try_get::<Option<String>, _>(idx)
-                .ok()
-                .flatten()
-                .map(JsonValue::String)
-                .unwrap_or(JsonValue::Null)
+            .and_then(|k| digits.get(k).copied())
+            .unwrap_or(0)
+    };
+
+    let mut text = String::new();
+    if sign == 0x4000 {
+        text.push('-');
+    }
+
+    if weight < 0 {
+        text.push('0');
+    } else {
+        text.push_str(&digit(0).to_string());
+        for k in 1..=weight {
+            text.push_str(&format!("{:04}", digit(k)));
+        }
+    }
+
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut k = weight + 1;
+        while fraction.len() < dscale {
+            fraction.push_str(&format!("{:04}", digit(k)));
+            k += 1;
         }
+        fraction.truncate(dscale);
+
+        text.push('.');
+        text.push_str(&fraction);
     }
+
+    Some(text)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn plan_cost_of_unexpected_plans_is_missing() {
+        let plan = serde_json::json!([{"Plan": {"Node Type": "Seq Scan", "Total Cost": 35.5}}]);
+        assert_eq!(plan_total_cost(&plan), Some(35.5));
+
+        assert_eq!(plan_total_cost(&serde_json::json!([])), None);
+        assert_eq!(plan_total_cost(&serde_json::json!([{"Plan": {}}])), None);
+        assert_eq!(
+            plan_total_cost(&serde_json::json!([{"Plan": {"Total Cost": "35.5"}}])),
+            None
+        );
+    }
+
     #[test]
     fn cast_present_simple() {
         assert!(has_explicit_cast("WHERE id = $1::int", 1));
@@ -453,10 +1475,7 @@ mod tests {
     #[test]
     fn multiple_occurrences_one_cast_is_enough() {
         // $1 used twice: once without cast, once with — should be considered "cast"
-        assert!(has_explicit_cast(
-            "WHERE id = $1 OR backup_id = $1::int",
-            1
-        ));
+        assert!(has_explicit_cast("WHERE id = $1 OR backup_id = $1::int", 1));
     }
 
     #[test]
@@ -474,6 +1493,99 @@ mod tests {
         assert!(!has_explicit_cast("VALUES ($1, $2, $3)", 4));
     }
 
+    #[tokio::test]
+    async fn resized_slots_respect_the_new_limit() {
+        let semaphore = Arc::new(Semaphore::new(3));
+        let held = semaphore.clone().acquire_many_owned(2).await.unwrap();
+
+        // One free slot is taken back now, the other once a held one is released
+        resize_slots(&semaphore, 3, 1);
+        assert_eq!(semaphore.available_permits(), 0);
+        tokio::task::yield_now().await;
+        drop(held);
+        assert_eq!(semaphore.available_permits(), 1);
+
+        resize_slots(&semaphore, 1, 4);
+        assert_eq!(semaphore.available_permits(), 4);
+    }
+
+    fn numeric_bytes(weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend((digits.len() as i16).to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(sign.to_be_bytes());
+        bytes.extend(dscale.to_be_bytes());
+        for digit in digits {
+            bytes.extend(digit.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn numeric_keeps_display_scale() {
+        // 12.50
+        let bytes = numeric_bytes(0, 0, 2, &[12, 5000]);
+        assert_eq!(numeric_binary_to_string(&bytes).unwrap(), "12.50");
+    }
+
+    #[test]
+    fn numeric_large_and_negative() {
+        // -123456789.0001
+        let bytes = numeric_bytes(2, 0x4000, 4, &[1, 2345, 6789, 1]);
+        assert_eq!(numeric_binary_to_string(&bytes).unwrap(), "-123456789.0001");
+
+        // 10000 (trailing zero groups are omitted on the wire)
+        let bytes = numeric_bytes(1, 0, 0, &[1]);
+        assert_eq!(numeric_binary_to_string(&bytes).unwrap(), "10000");
+    }
+
+    #[test]
+    fn numeric_small_fraction() {
+        // 0.00001234
+        let bytes = numeric_bytes(-2, 0, 8, &[1234]);
+        assert_eq!(numeric_binary_to_string(&bytes).unwrap(), "0.00001234");
+
+        // 0
+        let bytes = numeric_bytes(0, 0, 0, &[]);
+        assert_eq!(numeric_binary_to_string(&bytes).unwrap(), "0");
+    }
+
+    #[test]
+    fn numeric_special_values() {
+        let bytes = numeric_bytes(0, 0xC000, 0, &[]);
+        assert_eq!(numeric_binary_to_string(&bytes).unwrap(), "NaN");
+    }
+
+    #[test]
+    fn interval_postgres_style() {
+        let interval = |months, days, microseconds| {
+            interval_to_string(&PgInterval {
+                months,
+                days,
+                microseconds,
+            })
+        };
+
+        assert_eq!(interval(0, 0, 0), "00:00:00");
+        assert_eq!(interval(14, 3, 0), "1 year 2 mons 3 days");
+        assert_eq!(interval(1, 1, 3_723_500_000), "1 mon 1 day 01:02:03.5");
+        assert_eq!(interval(0, -3, 3_600_000_000), "-3 days +01:00:00");
+        assert_eq!(interval(0, 0, -90_000_000), "-00:01:30");
+    }
+
+    #[test]
+    fn money_in_major_units() {
+        assert_eq!(money_to_json(PgMoney(1234)), "12.34");
+        assert_eq!(money_to_json(PgMoney(-5)), "-0.05");
+    }
+
+    #[test]
+    fn non_finite_floats_as_strings() {
+        assert_eq!(float_to_json(1.5), 1.5);
+        assert_eq!(float_to_json(f64::NAN), "NaN");
+        assert_eq!(float_to_json(f64::NEG_INFINITY), "-Infinity");
+    }
+
     #[test]
     fn double_digit_param_index() {
         let sql = "INSERT INTO t (a, b, c, d, e, f, g, h, i, j) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::int)";
@@ -1,9 +1,19 @@
+#[cfg(feature = "server")]
+pub mod admin;
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod auth;
+mod cancel;
 pub mod config;
+pub mod csv;
+pub mod cursor;
 pub mod error;
 pub mod executor;
+mod params;
 pub mod parser;
+mod pools;
 pub mod store;
+mod text_form;
 pub mod token;
 
 #[cfg(feature = "server")]
@@ -11,5 +21,9 @@ pub mod server;
 
 // Re-export main types for convenience
 pub use config::{DatabaseBackend, DatabaseConfig, QueryRules, SqlOperation};
-pub use executor::{ExecutorError, ExecutorPool, QueryRequest, QueryResponse, has_explicit_cast};
-pub use parser::{ParseError, ParsedQuery, parse_and_validate};
+pub use executor::{
+    ColumnInfo, ColumnarResponse, ExecuteOptions, ExecutorError, ExecutorPool, QueryRequest,
+    QueryResponse, QueryResult, TransactionRequest, TransactionResponse, TransactionStatement,
+    has_explicit_cast,
+};
+pub use parser::{ParseError, ParsedQuery, parse_and_validate, parse_and_validate_with_rules};
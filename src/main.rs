@@ -7,8 +7,8 @@ use uuid::Uuid;
 use postgate::config::{Config, ServerConfig};
 use postgate::executor::ExecutorPool;
 use postgate::server::{AppState, configure_routes};
-use postgate::store::Store;
-use postgate::token::generate_token;
+use postgate::store::{Store, TokenRestrictions};
+use postgate::token::{from_now, generate_token, parse_ttl};
 
 /// Secure HTTP proxy for PostgreSQL with SQL validation and multi-tenant support
 #[derive(Parser)]
@@ -49,9 +49,43 @@ enum Commands {
         #[arg(default_value = "default")]
         name: String,
 
-        /// Comma-separated permissions: SELECT,INSERT,UPDATE,DELETE,CREATE,ALTER,DROP
+        /// Comma-separated permissions: SELECT,INSERT,UPDATE,DELETE,CREATE,ALTER,DROP,EXPLAIN
         #[arg(short, long, default_value = "SELECT,INSERT,UPDATE,DELETE")]
         permissions: String,
+
+        /// Token lifetime, e.g. 30m, 12h or 7d (never expires if omitted)
+        #[arg(long)]
+        ttl: Option<String>,
+
+        /// Comma-separated tables the token is restricted to (every table if omitted)
+        #[arg(long)]
+        tables: Option<String>,
+
+        /// Comma-separated tables the token can never access
+        #[arg(long)]
+        deny_tables: Option<String>,
+
+        /// Comma-separated functions the token can call even if denied by default
+        #[arg(long)]
+        allow_functions: Option<String>,
+
+        /// Comma-separated functions the token can never call
+        #[arg(long)]
+        deny_functions: Option<String>,
+
+        /// Reject UPDATE and DELETE without a WHERE clause that filters rows
+        #[arg(long)]
+        safe_updates: bool,
+    },
+
+    /// Issue a new secret for a token, the old one stays valid during the grace period
+    RotateToken {
+        /// Token UUID
+        token_id: String,
+
+        /// How long the old secret stays valid, e.g. 30m, 12h or 7d
+        #[arg(short, long, default_value = "1h")]
+        grace: String,
     },
 }
 
@@ -139,6 +173,8 @@ async fn generate_token_command(
     database_id: &str,
     name: &str,
     permissions_str: &str,
+    ttl: Option<&str>,
+    restrictions: &TokenRestrictions,
     config: &Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let pool = sqlx::PgPool::connect(&config.database_url).await?;
@@ -156,7 +192,7 @@ async fn generate_token_command(
 
     // Validate permissions
     let valid_ops = [
-        "SELECT", "INSERT", "UPDATE", "DELETE", "CREATE", "ALTER", "DROP",
+        "SELECT", "INSERT", "UPDATE", "DELETE", "CREATE", "ALTER", "DROP", "EXPLAIN",
     ];
     for perm in &permissions {
         if !valid_ops.contains(perm) {
@@ -164,6 +200,8 @@ async fn generate_token_command(
         }
     }
 
+    let expires_at = ttl.map(parse_ttl).transpose()?.map(from_now).transpose()?;
+
     // Delete existing token with same name (if any), then insert new one
     // Note: Using DELETE + INSERT instead of ON CONFLICT for view compatibility
     sqlx::query("DELETE FROM postgate_tokens WHERE database_id = $1 AND name = $2")
@@ -174,8 +212,9 @@ async fn generate_token_command(
 
     sqlx::query(
         r#"
-        INSERT INTO postgate_tokens (database_id, name, token_hash, token_prefix, allowed_operations)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO postgate_tokens (database_id, name, token_hash, token_prefix, allowed_operations, expires_at,
+            allowed_tables, denied_tables, allowed_functions, denied_functions, safe_updates)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         "#,
     )
     .bind(db_id)
@@ -183,11 +222,55 @@ async fn generate_token_command(
     .bind(&token_hash)
     .bind(&token_prefix)
     .bind(&permissions)
+    .bind(expires_at)
+    .bind(&restrictions.allowed_tables)
+    .bind(&restrictions.denied_tables)
+    .bind(&restrictions.allowed_functions)
+    .bind(&restrictions.denied_functions)
+    .bind(restrictions.safe_updates)
     .execute(&pool)
     .await?;
 
     println!("{}", token);
 
+    if let Some(expires_at) = expires_at {
+        eprintln!("Expires at: {}", expires_at.to_rfc3339());
+    }
+
+    Ok(())
+}
+
+fn parse_name_list(names: &str) -> Vec<String> {
+    names
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+async fn rotate_token_command(
+    token_id: &str,
+    grace: &str,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = sqlx::PgPool::connect(&config.database_url).await?;
+
+    let token_id: Uuid = token_id
+        .parse()
+        .map_err(|_| format!("Invalid token ID: {}", token_id))?;
+
+    let grace = parse_ttl(grace)?;
+    let valid_until = from_now(grace)?;
+
+    let row: (String,) = sqlx::query_as("SELECT token FROM rotate_tenant_token($1, $2)")
+        .bind(token_id)
+        .bind(grace)
+        .fetch_one(&pool)
+        .await?;
+
+    println!("{}", row.0);
+    eprintln!("Previous token valid until: {}", valid_until.to_rfc3339());
+
     Ok(())
 }
 
@@ -222,15 +305,52 @@ async fn main() -> std::io::Result<()> {
                 database_id,
                 name,
                 permissions,
+                ttl,
+                tables,
+                deny_tables,
+                allow_functions,
+                deny_functions,
+                safe_updates,
             } => {
-                if let Err(e) =
-                    generate_token_command(&database_id, &name, &permissions, &config).await
+                let restrictions = TokenRestrictions {
+                    allowed_tables: tables.as_deref().map(parse_name_list),
+                    denied_tables: deny_tables
+                        .as_deref()
+                        .map(parse_name_list)
+                        .unwrap_or_default(),
+                    allowed_functions: allow_functions
+                        .as_deref()
+                        .map(parse_name_list)
+                        .unwrap_or_default(),
+                    denied_functions: deny_functions
+                        .as_deref()
+                        .map(parse_name_list)
+                        .unwrap_or_default(),
+                    safe_updates,
+                };
+
+                if let Err(e) = generate_token_command(
+                    &database_id,
+                    &name,
+                    &permissions,
+                    ttl.as_deref(),
+                    &restrictions,
+                    &config,
+                )
+                .await
                 {
                     eprintln!("Error: {}", e);
                     std::process::exit(1);
                 }
                 return Ok(());
             }
+            Commands::RotateToken { token_id, grace } => {
+                if let Err(e) = rotate_token_command(&token_id, &grace, &config).await {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
         }
     }
 
@@ -0,0 +1,427 @@
+//! Typed query parameters
+//!
+//! An entry of `params` can name its Postgres type instead of having it guessed
+//! from the JSON value: `{"$type": "int8[]", "value": [1, 2]}`,
+//! `{"$type": "bytea", "base64": "AP8Q"}`. The value is then bound with exactly
+//! that type, so no cast is needed in the SQL. The `$type` key is reserved for this,
+//! so JSONB payloads with a `type` key are still bound as they are.
+
+use serde_json::Value as JsonValue;
+use sqlx::Postgres;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgArguments, PgHasArrayType, PgTypeInfo};
+
+type PgQuery<'q> = sqlx::query::Query<'q, Postgres, PgArguments>;
+
+/// Types accepted in the typed form, arrays of them are written with `[]`
+pub const PARAM_TYPES: &[&str] = &[
+    "bool",
+    "int2",
+    "int4",
+    "int8",
+    "float4",
+    "float8",
+    "numeric",
+    "text",
+    "uuid",
+    "bytea",
+    "date",
+    "time",
+    "timestamp",
+    "timestamptz",
+    "jsonb",
+];
+
+/// Other spellings of the supported types
+const TYPE_ALIASES: &[(&str, &str)] = &[
+    ("boolean", "bool"),
+    ("smallint", "int2"),
+    ("int", "int4"),
+    ("integer", "int4"),
+    ("bigint", "int8"),
+    ("real", "float4"),
+    ("double precision", "float8"),
+    ("decimal", "numeric"),
+    ("varchar", "text"),
+];
+
+/// Key naming the type of a typed parameter
+const TYPE_KEY: &str = "$type";
+
+/// A parameter in typed form
+pub(crate) struct TypedParam<'a> {
+    /// Declared type as sent
+    type_name: &'a str,
+    value: &'a JsonValue,
+    /// Given as `base64` rather than `value` (bytea)
+    base64: bool,
+}
+
+/// Typed form of a parameter: an object with only `$type` and either `value` or
+/// `base64`. Anything else, e.g. a JSONB payload like `{"type": "text", "value": "hi"}`,
+/// is an untyped value.
+pub(crate) fn typed_param(value: &JsonValue) -> Option<TypedParam<'_>> {
+    let object = value.as_object()?;
+    if object.len() != 2 {
+        return None;
+    }
+
+    let type_name = object.get(TYPE_KEY)?.as_str()?;
+
+    match (object.get("value"), object.get("base64")) {
+        (Some(value), None) => Some(TypedParam {
+            type_name,
+            value,
+            base64: false,
+        }),
+        (None, Some(value)) => Some(TypedParam {
+            type_name,
+            value,
+            base64: true,
+        }),
+        _ => None,
+    }
+}
+
+/// Supported element type of a type name (`int8`, `bigint[]`), and whether it is an array
+fn supported_type(type_name: &str) -> Option<(&'static str, bool)> {
+    let (element, is_array) = match type_name.strip_suffix("[]") {
+        Some(element) => (element, true),
+        None => (type_name, false),
+    };
+    let element = element.trim().to_ascii_lowercase();
+
+    let element = TYPE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == element)
+        .map(|(_, name)| *name)
+        .or_else(|| PARAM_TYPES.iter().copied().find(|name| *name == element))?;
+
+    Some((element, is_array))
+}
+
+/// Bind a typed parameter with its Postgres type. The error describes what is
+/// wrong with the parameter.
+pub(crate) fn bind_typed<'q>(
+    query: PgQuery<'q>,
+    param: TypedParam<'q>,
+) -> Result<PgQuery<'q>, String> {
+    let (element, is_array) = supported_type(param.type_name)
+        .ok_or_else(|| format!("unsupported type {}", param.type_name))?;
+
+    if param.base64 != (element == "bytea") {
+        return Err(match param.base64 {
+            true => format!("base64 is only accepted for bytea, not {}", param.type_name),
+            false => "bytea is given as base64".to_string(),
+        });
+    }
+
+    let value = param.value;
+    match element {
+        "bool" => bind_as(query, value, is_array, element, JsonValue::as_bool),
+        "int2" => bind_as(query, value, is_array, element, |v| {
+            integer(v)?.try_into().ok().map(|v: i16| v)
+        }),
+        "int4" => bind_as(query, value, is_array, element, |v| {
+            integer(v)?.try_into().ok().map(|v: i32| v)
+        }),
+        "int8" => bind_as(query, value, is_array, element, integer),
+        "float4" => bind_as(query, value, is_array, element, |v| {
+            float(v).map(|v| v as f32)
+        }),
+        "float8" => bind_as(query, value, is_array, element, float),
+        "numeric" => bind_as(query, value, is_array, element, |v| {
+            // JSON numbers may be written with an exponent (1e20)
+            let text = match v {
+                JsonValue::Number(n) => expand_exponent(&n.to_string())?,
+                JsonValue::String(s) => expand_exponent(s)?,
+                _ => return None,
+            };
+            decimal_to_numeric_binary(&text).map(NumericParam)
+        }),
+        "text" => bind_as(query, value, is_array, element, |v| {
+            v.as_str().map(str::to_string)
+        }),
+        "uuid" => bind_as(query, value, is_array, element, |v| {
+            v.as_str()?.parse::<uuid::Uuid>().ok()
+        }),
+        "bytea" => bind_as(query, value, is_array, element, |v| {
+            use base64::Engine;
+
+            base64::engine::general_purpose::STANDARD
+                .decode(v.as_str()?)
+                .ok()
+        }),
+        "date" => bind_as(query, value, is_array, element, |v| {
+            v.as_str()?.parse::<chrono::NaiveDate>().ok()
+        }),
+        "time" => bind_as(query, value, is_array, element, |v| {
+            v.as_str()?.parse::<chrono::NaiveTime>().ok()
+        }),
+        "timestamp" => bind_as(query, value, is_array, element, |v| {
+            let text = v.as_str()?;
+            text.parse::<chrono::NaiveDateTime>()
+                .or_else(|_| chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f"))
+                .ok()
+        }),
+        "timestamptz" => bind_as(query, value, is_array, element, |v| {
+            chrono::DateTime::parse_from_rfc3339(v.as_str()?)
+                .ok()
+                .map(|v| v.with_timezone(&chrono::Utc))
+        }),
+        "jsonb" => bind_as(query, value, is_array, element, |v| Some(v.clone())),
+        _ => Err(format!("unsupported type {}", param.type_name)),
+    }
+}
+
+/// Bind `value` as a `T`, or as an array of `T`. JSON nulls are SQL NULLs.
+fn bind_as<'q, T>(
+    query: PgQuery<'q>,
+    value: &JsonValue,
+    is_array: bool,
+    type_name: &str,
+    convert: impl Fn(&JsonValue) -> Option<T>,
+) -> Result<PgQuery<'q>, String>
+where
+    T: sqlx::Encode<'q, Postgres> + sqlx::Type<Postgres> + PgHasArrayType + 'q,
+{
+    let element = |value: &JsonValue| match value {
+        JsonValue::Null => Ok(None),
+        value => convert(value)
+            .map(Some)
+            .ok_or_else(|| format!("{} is not a valid {}", value, type_name)),
+    };
+
+    if !is_array {
+        return Ok(query.bind(element(value)?));
+    }
+
+    match value {
+        JsonValue::Null => Ok(query.bind(None::<Vec<Option<T>>>)),
+        JsonValue::Array(values) => {
+            let values = values.iter().map(element).collect::<Result<Vec<_>, _>>()?;
+            Ok(query.bind(values))
+        }
+        value => Err(format!("{} is not an array", value)),
+    }
+}
+
+fn integer(value: &JsonValue) -> Option<i64> {
+    match value {
+        JsonValue::Number(n) => n.as_i64(),
+        // Strings keep int8 values beyond what JavaScript numbers hold exactly
+        JsonValue::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn float(value: &JsonValue) -> Option<f64> {
+    match value {
+        JsonValue::Number(n) => n.as_f64(),
+        // "NaN", "Infinity" and "-Infinity" have no JSON number form
+        JsonValue::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// NUMERIC in its binary wire format, converted from a decimal string
+struct NumericParam(Vec<u8>);
+
+impl sqlx::Type<Postgres> for NumericParam {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("numeric")
+    }
+}
+
+impl PgHasArrayType for NumericParam {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_numeric")
+    }
+}
+
+impl sqlx::Encode<'_, Postgres> for NumericParam {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        buf.extend_from_slice(&self.0);
+        Ok(IsNull::No)
+    }
+}
+
+/// Largest exponent accepted, the range of NUMERIC (131072 digits before the point)
+const MAX_EXPONENT: i64 = 131_072;
+
+/// Decimal string of a number in exponent form, as Postgres reads it:
+/// `1.50e1` is `15.0`, `1e-3` is `0.001`. Other strings are returned as is.
+fn expand_exponent(text: &str) -> Option<String> {
+    let Some((mantissa, exponent)) = text.split_once(['e', 'E']) else {
+        return Some(text.to_string());
+    };
+    let exponent: i64 = exponent
+        .parse()
+        .ok()
+        .filter(|e: &i64| e.abs() <= MAX_EXPONENT)?;
+
+    let (sign, unsigned) = match mantissa.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (int_part, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let digits = format!("{int_part}{fraction}");
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Position of the decimal point in `digits`
+    let point = int_part.len() as i64 + exponent;
+    let expanded = if point <= 0 {
+        format!("0.{}{}", "0".repeat(-point as usize), digits)
+    } else if point as usize >= digits.len() {
+        format!("{}{}", digits, "0".repeat(point as usize - digits.len()))
+    } else {
+        let (int_part, fraction) = digits.split_at(point as usize);
+        format!("{int_part}.{fraction}")
+    };
+
+    Some(format!("{sign}{expanded}"))
+}
+
+/// Binary NUMERIC of a decimal string (`-12.50`, `NaN`, `Infinity`), keeping its
+/// scale. Layout: ndigits, weight, sign, dscale, then base-10000 digits.
+fn decimal_to_numeric_binary(text: &str) -> Option<Vec<u8>> {
+    let header = |ndigits: i16, weight: i16, sign: u16, dscale: u16| {
+        [
+            ndigits.to_be_bytes(),
+            weight.to_be_bytes(),
+            sign.to_be_bytes(),
+            dscale.to_be_bytes(),
+        ]
+        .concat()
+    };
+
+    match text {
+        "NaN" => return Some(header(0, 0, 0xC000, 0)),
+        "Infinity" => return Some(header(0, 0, 0xD000, 0)),
+        "-Infinity" => return Some(header(0, 0, 0xF000, 0)),
+        _ => {}
+    }
+
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (int_part, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if (int_part.is_empty() && fraction.is_empty()) || !is_digits(int_part) || !is_digits(fraction)
+    {
+        return None;
+    }
+
+    // Pad both parts to whole base-10000 digits around the decimal point
+    let int_pad = (4 - int_part.len() % 4) % 4;
+    let fraction_pad = (4 - fraction.len() % 4) % 4;
+    let padded = format!(
+        "{}{}{}{}",
+        "0".repeat(int_pad),
+        int_part,
+        fraction,
+        "0".repeat(fraction_pad)
+    );
+
+    let mut digits: Vec<i16> = padded
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| std::str::from_utf8(chunk).ok()?.parse().ok())
+        .collect::<Option<_>>()?;
+    let mut weight = ((int_pad + int_part.len()) / 4) as i64 - 1;
+
+    let leading_zeros = digits.iter().take_while(|&&d| d == 0).count();
+    digits.drain(..leading_zeros);
+    weight -= leading_zeros as i64;
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+
+    let (weight, sign) = match digits.is_empty() {
+        true => (0, 0),
+        false => (weight, if negative { 0x4000 } else { 0 }),
+    };
+
+    let mut bytes = header(
+        i16::try_from(digits.len()).ok()?,
+        i16::try_from(weight).ok()?,
+        sign,
+        u16::try_from(fraction.len()).ok()?,
+    );
+    for digit in digits {
+        bytes.extend(digit.to_be_bytes());
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::numeric_binary_to_string;
+    use serde_json::json;
+
+    fn roundtrip(text: &str) -> String {
+        numeric_binary_to_string(&decimal_to_numeric_binary(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn numeric_roundtrip() {
+        assert_eq!(roundtrip("12.50"), "12.50");
+        assert_eq!(roundtrip("-123456789.000123"), "-123456789.000123");
+        assert_eq!(roundtrip("0.00001234"), "0.00001234");
+        assert_eq!(roundtrip("10000"), "10000");
+        assert_eq!(roundtrip("+7"), "7");
+        assert_eq!(roundtrip(".5"), "0.5");
+        assert_eq!(roundtrip("-0.00"), "0.00");
+        assert_eq!(roundtrip("NaN"), "NaN");
+    }
+
+    #[test]
+    fn numeric_exponent() {
+        let expand = |text: &str| expand_exponent(text).map(|text| roundtrip(&text));
+
+        assert_eq!(expand("1e20").unwrap(), "100000000000000000000");
+        assert_eq!(expand("1.50e1").unwrap(), "15.0");
+        assert_eq!(expand("-1.5E-3").unwrap(), "-0.0015");
+        assert_eq!(expand("12.5e+1").unwrap(), "125");
+        assert_eq!(expand("2.5").unwrap(), "2.5");
+        assert!(expand_exponent("e5").is_none());
+        assert!(expand_exponent("1e").is_none());
+        assert!(expand_exponent("1e999999999").is_none());
+    }
+
+    #[test]
+    fn numeric_invalid() {
+        assert!(decimal_to_numeric_binary("").is_none());
+        assert!(decimal_to_numeric_binary("1e5").is_none());
+        assert!(decimal_to_numeric_binary("1.2.3").is_none());
+        assert!(decimal_to_numeric_binary("-").is_none());
+    }
+
+    #[test]
+    fn typed_form_detection() {
+        assert!(typed_param(&json!({"$type": "int8", "value": 1})).is_some());
+        assert!(typed_param(&json!({"$type": "bytea", "base64": "AA=="})).is_some());
+        assert!(typed_param(&json!({"$type": "BIGINT[]", "value": [1]})).is_some());
+
+        // Other objects stay JSONB values, even when `type` names a supported type
+        assert!(typed_param(&json!({"type": "text", "value": "hi"})).is_none());
+        assert!(typed_param(&json!({"type": "date", "value": "2024-01-01"})).is_none());
+        assert!(typed_param(&json!({"$type": "int8"})).is_none());
+        assert!(typed_param(&json!({"$type": "int8", "value": 1, "x": 2})).is_none());
+        assert!(typed_param(&json!({"$type": 1, "value": 1})).is_none());
+    }
+
+    #[test]
+    fn supported_types() {
+        assert_eq!(supported_type("BIGINT[]"), Some(("int8", true)));
+        assert_eq!(supported_type("double precision"), Some(("float8", false)));
+        assert_eq!(supported_type("point"), None);
+    }
+}
@@ -1,8 +1,13 @@
-use crate::config::SqlOperation;
-use sqlparser::ast::{Statement, visit_relations};
+use crate::config::{QueryLimits, QueryRules, SqlOperation};
+use sqlparser::ast::{
+    BinaryOperator, Expr, FromTable, ObjectName, ObjectType, Query, SetExpr, Statement,
+    TableFactor, TableWithJoins, UpdateTableFromKind, UtilityOption, Value, Visit, Visitor,
+    visit_expressions, visit_relations, visit_statements,
+};
 use sqlparser::dialect::PostgreSqlDialect;
 use sqlparser::parser::Parser;
 use std::collections::HashSet;
+use std::ops::ControlFlow;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -19,6 +24,9 @@ pub enum ParseError {
     #[error("Operation {0} is not allowed")]
     OperationNotAllowed(SqlOperation),
 
+    #[error("{0} must have a WHERE clause that filters rows (safe updates)")]
+    UnfilteredWrite(SqlOperation),
+
     #[error("Table '{0}' is not allowed")]
     TableNotAllowed(String),
 
@@ -28,28 +36,71 @@ pub enum ParseError {
     #[error("Qualified table names are not allowed: '{0}'")]
     QualifiedTableName(String),
 
+    #[error("Function '{0}' is not allowed")]
+    FunctionNotAllowed(String),
+
     #[error("System table access is not allowed: '{0}'")]
     SystemTableAccess(String),
 
     #[error("Unsupported statement type")]
     UnsupportedStatement,
+
+    #[error("EXPLAIN ANALYZE is not allowed, use EXPLAIN without ANALYZE")]
+    ExplainAnalyze,
+
+    #[error("Query exceeds {limit}: {value} (limit: {max})")]
+    LimitExceeded {
+        limit: &'static str,
+        value: usize,
+        max: i32,
+    },
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct ParsedQuery {
+    /// Operation of the top-level statement
     pub operation: SqlOperation,
+    /// Every operation the statement performs, including nested ones
+    pub operations: HashSet<SqlOperation>,
     pub tables: HashSet<String>,
     pub statement: Statement,
     pub returns_rows: bool,
 }
 
+impl ParsedQuery {
+    /// A SELECT without data-modifying CTEs: rolling it back loses nothing
+    pub fn is_read_only(&self) -> bool {
+        self.operation == SqlOperation::Select
+            && self.operations.iter().all(|op| *op == SqlOperation::Select)
+    }
+}
+
 /// Parse and validate SQL query
-/// - allowed_operations: comes from the token
+/// - allowed_operations: comes from the token, empty allows every operation
+///
+/// Only checks operations, see [`parse_and_validate_with_rules`] for the other token rules.
 pub fn parse_and_validate(
     sql: &str,
     allowed_operations: &HashSet<SqlOperation>,
 ) -> Result<ParsedQuery, ParseError> {
+    let rules = QueryRules {
+        allowed_operations: allowed_operations.clone(),
+        ..Default::default()
+    };
+
+    parse_and_validate_with_rules(sql, &rules)
+}
+
+/// Parse and validate SQL query
+/// - rules: allowed operations and tables, from the token
+pub fn parse_and_validate_with_rules(
+    sql: &str,
+    rules: &QueryRules,
+) -> Result<ParsedQuery, ParseError> {
+    // Checked first, so oversized queries are never parsed
+    check_limit("max_sql_length", sql.len(), rules.limits.max_sql_length)?;
+
     let dialect = PostgreSqlDialect {};
     let statements = Parser::parse_sql(&dialect, sql)?;
 
@@ -63,20 +114,37 @@ pub fn parse_and_validate(
 
     let statement = statements.into_iter().next().unwrap();
     let operation = extract_operation(&statement)?;
+    let collected = collect_operations(&statement)?;
 
     // Extract and validate table references (blocks qualified names, pg_*, etc.)
-    let table_refs = extract_table_refs(&statement);
+    let mut table_refs = extract_table_refs(&statement);
+    table_refs.extend(collected.into_targets);
     let tables = validate_table_refs(&table_refs)?;
 
-    // Validate operation against token's allowed_operations
-    if !allowed_operations.is_empty() && !allowed_operations.contains(&operation) {
-        return Err(ParseError::OperationNotAllowed(operation));
+    // Validate every operation against token's allowed_operations,
+    // so a data-modifying CTE can't hide behind a SELECT
+    for op in &collected.operations {
+        if !rules.allows_operation(*op) {
+            return Err(ParseError::OperationNotAllowed(*op));
+        }
     }
 
+    validate_table_rules(&table_refs, rules)?;
+
+    if rules.safe_updates {
+        validate_safe_updates(&statement)?;
+    }
+
+    // Validate every function call (pg_sleep, set_config, lo_import, ...)
+    validate_functions(&extract_function_refs(&statement), rules)?;
+
+    validate_complexity(&statement, &rules.limits)?;
+
     let returns_rows = check_returns_rows(&statement);
 
     Ok(ParsedQuery {
         operation,
+        operations: collected.operations.into_iter().collect(),
         tables,
         statement,
         returns_rows,
@@ -85,7 +153,13 @@ pub fn parse_and_validate(
 
 fn extract_operation(statement: &Statement) -> Result<SqlOperation, ParseError> {
     match statement {
-        Statement::Query(_) => Ok(SqlOperation::Select),
+        Statement::Query(query) => match &*query.body {
+            // WITH ... INSERT/UPDATE/DELETE is parsed as a query
+            SetExpr::Insert(dml) | SetExpr::Update(dml) | SetExpr::Delete(dml) => {
+                extract_operation(dml)
+            }
+            _ => Ok(SqlOperation::Select),
+        },
         Statement::Insert(_) => Ok(SqlOperation::Insert),
         Statement::Update(_) => Ok(SqlOperation::Update),
         Statement::Delete(_) => Ok(SqlOperation::Delete),
@@ -95,14 +169,171 @@ fn extract_operation(statement: &Statement) -> Result<SqlOperation, ParseError>
         | Statement::CreateView { .. } => Ok(SqlOperation::Create),
         Statement::AlterTable { .. } | Statement::AlterIndex { .. } => Ok(SqlOperation::Alter),
         Statement::Drop { .. } | Statement::Truncate { .. } => Ok(SqlOperation::Drop),
+        // ANALYZE runs the statement, which would get around max_plan_cost
+        Statement::Explain {
+            analyze, options, ..
+        } => {
+            if *analyze || options.iter().flatten().any(is_analyze_option) {
+                return Err(ParseError::ExplainAnalyze);
+            }
+            Ok(SqlOperation::Explain)
+        }
         _ => Err(ParseError::UnsupportedStatement),
     }
 }
 
+/// True for `ANALYZE` (or its spelling `ANALYSE`) in `EXPLAIN (...)`, unless
+/// explicitly turned off
+fn is_analyze_option(option: &UtilityOption) -> bool {
+    let name = &option.name.value;
+    if !name.eq_ignore_ascii_case("analyze") && !name.eq_ignore_ascii_case("analyse") {
+        return false;
+    }
+
+    match &option.arg {
+        Some(Expr::Value(value)) => value.value != Value::Boolean(false),
+        Some(Expr::Identifier(ident)) => !ident.value.eq_ignore_ascii_case("off"),
+        _ => true,
+    }
+}
+
+/// Operations found while walking the whole statement, in visit order
+#[derive(Default)]
+struct OperationCollector {
+    operations: Vec<SqlOperation>,
+    /// SELECT ... INTO targets, which the relation visitor doesn't report
+    into_targets: Vec<TableRef>,
+}
+
+impl OperationCollector {
+    fn add(&mut self, operation: SqlOperation) {
+        if !self.operations.contains(&operation) {
+            self.operations.push(operation);
+        }
+    }
+
+    fn collect_select_into(&mut self, body: &SetExpr) {
+        match body {
+            SetExpr::Select(select) => {
+                if let Some(into) = &select.into {
+                    self.add(SqlOperation::Create);
+                    self.into_targets.push(table_ref(&into.name));
+                }
+            }
+            SetExpr::SetOperation { left, right, .. } => {
+                self.collect_select_into(left);
+                self.collect_select_into(right);
+            }
+            // Nested queries and statements are visited on their own
+            _ => {}
+        }
+    }
+}
+
+impl Visitor for OperationCollector {
+    type Break = ParseError;
+
+    // Called for the top-level statement and for DML nested in CTEs
+    fn pre_visit_statement(&mut self, statement: &Statement) -> ControlFlow<ParseError> {
+        match extract_operation(statement) {
+            Ok(operation) => {
+                self.add(operation);
+                ControlFlow::Continue(())
+            }
+            Err(e) => ControlFlow::Break(e),
+        }
+    }
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<ParseError> {
+        // FOR UPDATE / FOR SHARE need UPDATE privilege in Postgres
+        if !query.locks.is_empty() {
+            self.add(SqlOperation::Update);
+        }
+
+        self.collect_select_into(&query.body);
+        ControlFlow::Continue(())
+    }
+}
+
+fn collect_operations(statement: &Statement) -> Result<OperationCollector, ParseError> {
+    let mut collector = OperationCollector::default();
+
+    match statement.visit(&mut collector) {
+        ControlFlow::Continue(()) => Ok(collector),
+        ControlFlow::Break(e) => Err(e),
+    }
+}
+
+/// Reject UPDATE and DELETE whose WHERE clause is missing or doesn't filter rows,
+/// including the ones in data-modifying CTEs
+fn validate_safe_updates(statement: &Statement) -> Result<(), ParseError> {
+    let result = visit_statements(statement, |statement| {
+        let (operation, selection) = match statement {
+            Statement::Update(update) => (SqlOperation::Update, &update.selection),
+            Statement::Delete(delete) => (SqlOperation::Delete, &delete.selection),
+            _ => return ControlFlow::Continue(()),
+        };
+
+        match selection {
+            Some(selection) if !is_tautology(selection) => ControlFlow::Continue(()),
+            _ => ControlFlow::Break(ParseError::UnfilteredWrite(operation)),
+        }
+    });
+
+    match result {
+        ControlFlow::Continue(()) => Ok(()),
+        ControlFlow::Break(e) => Err(e),
+    }
+}
+
+/// Whether a WHERE clause matches every row: it is constant (`1=1`, `true`), compares a
+/// column with itself (`id = id`) or has such an OR branch. Subqueries (`EXISTS (...)`)
+/// depend on the data and count as filters.
+fn is_tautology(expr: &Expr) -> bool {
+    match expr {
+        Expr::Nested(inner) => is_tautology(inner),
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Or,
+            right,
+        } => is_tautology(left) || is_tautology(right),
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => is_tautology(left) && is_tautology(right),
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Eq | BinaryOperator::GtEq | BinaryOperator::LtEq,
+            right,
+        } if left == right => true,
+        _ => is_constant(expr),
+    }
+}
+
+/// No column or subquery in the expression
+fn is_constant(expr: &Expr) -> bool {
+    !visit_expressions(expr, |expr| match expr {
+        Expr::Identifier(_)
+        | Expr::CompoundIdentifier(_)
+        | Expr::Exists { .. }
+        | Expr::Subquery(_)
+        | Expr::InSubquery { .. } => ControlFlow::Break(()),
+        _ => ControlFlow::Continue(()),
+    })
+    .is_break()
+}
+
 /// Check if the statement returns rows (SELECT or DML with RETURNING)
 fn check_returns_rows(statement: &Statement) -> bool {
     match statement {
-        Statement::Query(_) => true,
+        Statement::Query(query) => match &*query.body {
+            SetExpr::Insert(dml) | SetExpr::Update(dml) | SetExpr::Delete(dml) => {
+                check_returns_rows(dml)
+            }
+            _ => true,
+        },
+        Statement::Explain { .. } => true,
         Statement::Insert(insert) => insert.returning.is_some(),
         Statement::Update(update) => update.returning.is_some(),
         Statement::Delete(delete) => delete.returning.is_some(),
@@ -115,54 +346,76 @@ fn check_returns_rows(statement: &Statement) -> bool {
 pub struct TableRef {
     pub schema: Option<String>,
     pub name: String,
+    /// Quoted names are case-sensitive, unquoted ones are folded by Postgres
+    pub quoted: bool,
 }
 
 fn extract_table_refs(statement: &Statement) -> Vec<TableRef> {
     let mut tables = Vec::new();
 
     let _ = visit_relations(statement, |relation| {
-        let parts: Vec<_> = relation
-            .0
-            .iter()
-            .filter_map(|i| match i {
-                sqlparser::ast::ObjectNamePart::Identifier(ident) => Some(ident.value.clone()),
-                _ => None,
-            })
-            .collect();
-
-        let table_ref = match parts.len() {
-            1 => TableRef {
-                schema: None,
-                name: parts[0].clone(),
-            },
-            2 => TableRef {
-                schema: Some(parts[0].clone()),
-                name: parts[1].clone(),
-            },
-            _ => TableRef {
-                schema: Some(parts[..parts.len() - 1].join(".")),
-                name: parts[parts.len() - 1].clone(),
-            },
-        };
-
-        tables.push(table_ref);
-        std::ops::ControlFlow::<()>::Continue(())
+        tables.push(table_ref(relation));
+        ControlFlow::<()>::Continue(())
     });
 
+    // Names the visitor doesn't report as relations
+    match statement {
+        Statement::Drop {
+            object_type: ObjectType::Table | ObjectType::View | ObjectType::MaterializedView,
+            names,
+            ..
+        } => tables.extend(names.iter().map(table_ref)),
+        Statement::CreateView(create_view) => tables.push(table_ref(&create_view.name)),
+        _ => {}
+    }
+
     tables
 }
 
+fn table_ref(relation: &ObjectName) -> TableRef {
+    let idents: Vec<_> = relation
+        .0
+        .iter()
+        .filter_map(|i| match i {
+            sqlparser::ast::ObjectNamePart::Identifier(ident) => Some(ident),
+            _ => None,
+        })
+        .collect();
+    let parts: Vec<_> = idents.iter().map(|ident| ident.value.clone()).collect();
+    let quoted = idents
+        .last()
+        .is_some_and(|ident| ident.quote_style.is_some());
+
+    match parts.len() {
+        1 => TableRef {
+            schema: None,
+            name: parts[0].clone(),
+            quoted,
+        },
+        2 => TableRef {
+            schema: Some(parts[0].clone()),
+            name: parts[1].clone(),
+            quoted,
+        },
+        _ => TableRef {
+            schema: Some(parts[..parts.len() - 1].join(".")),
+            name: parts[parts.len() - 1].clone(),
+            quoted,
+        },
+    }
+}
+
 fn validate_table_refs(table_refs: &[TableRef]) -> Result<HashSet<String>, ParseError> {
     let mut table_names = HashSet::new();
 
     for table_ref in table_refs {
         // Block qualified names (schema.table)
         // Exception: postgate_helpers contains utility functions (list_tables, describe_table)
-        if let Some(schema) = &table_ref.schema {
-            if schema != "postgate_helpers" {
-                let full_name = format!("{}.{}", schema, table_ref.name);
-                return Err(ParseError::QualifiedTableName(full_name));
-            }
+        if let Some(schema) = &table_ref.schema
+            && schema != "postgate_helpers"
+        {
+            let full_name = format!("{}.{}", schema, table_ref.name);
+            return Err(ParseError::QualifiedTableName(full_name));
         }
 
         let name_lower = table_ref.name.to_lowercase();
@@ -183,23 +436,310 @@ fn validate_table_refs(table_refs: &[TableRef]) -> Result<HashSet<String>, Parse
     Ok(table_names)
 }
 
+/// Check tables against the token's allow and deny lists
+/// Unquoted identifiers are case-insensitive in Postgres, so names are compared ignoring case.
+/// CTE names are checked like tables.
+fn validate_table_rules(table_refs: &[TableRef], rules: &QueryRules) -> Result<(), ParseError> {
+    // Like Postgres, `"EVENTS"` is another table than `events`, `EVENTS` is the same one
+    let contains = |list: &HashSet<String>, table: &TableRef| {
+        list.iter().any(|name| match table.quoted {
+            true => *name == table.name,
+            false => name.eq_ignore_ascii_case(&table.name),
+        })
+    };
+
+    for table in table_refs {
+        if contains(&rules.denied_tables, table) {
+            return Err(ParseError::TableDenied(table.name.clone()));
+        }
+
+        if let Some(allowed_tables) = &rules.allowed_tables
+            && !contains(allowed_tables, table)
+        {
+            return Err(ParseError::TableNotAllowed(table.name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Functions denied unless the token allows them, a trailing '*' matches a prefix
+const DEFAULT_DENIED_FUNCTIONS: &[&str] = &[
+    // Holding connections of the shared pool
+    "pg_sleep*",
+    // Settings (search_path escapes the tenant schema)
+    "set_config",
+    "current_setting",
+    // Server files and large objects
+    "pg_read_*",
+    "pg_ls_*",
+    "pg_stat_file",
+    "pg_file_*",
+    "lo_*",
+    "loread",
+    "lowrite",
+    // Remote connections
+    "dblink*",
+    // Other backends and server administration
+    "pg_terminate_backend",
+    "pg_cancel_backend",
+    "pg_reload_conf",
+    "pg_rotate_logfile",
+    "pg_promote",
+    "pg_switch_wal",
+    "pg_backup_*",
+    "pg_create_*",
+    "pg_drop_replication_slot",
+    "pg_logical_*",
+    "pg_replication_*",
+    "pg_advisory_*",
+    "pg_try_advisory_*",
+    // Activity of other backends, including the query text of other tenants
+    "pg_stat_get_activity",
+    "pg_stat_get_backend_*",
+    // Run SQL from strings or dump whole schemas, bypassing validation
+    "query_to_xml*",
+    "cursor_to_xml*",
+    "table_to_xml*",
+    "schema_to_xml*",
+    "database_to_xml*",
+    "ts_stat",
+    "ts_rewrite",
+];
+
+/// Collects function calls, in expressions and in FROM clauses
+#[derive(Default)]
+struct FunctionCollector {
+    functions: Vec<TableRef>,
+}
+
+impl Visitor for FunctionCollector {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<()> {
+        if let Expr::Function(function) = expr {
+            self.functions.push(table_ref(&function.name));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<()> {
+        match table_factor {
+            TableFactor::Table {
+                name,
+                args: Some(_),
+                ..
+            }
+            | TableFactor::Function { name, .. } => self.functions.push(table_ref(name)),
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+fn extract_function_refs(statement: &Statement) -> Vec<TableRef> {
+    let mut collector = FunctionCollector::default();
+    let _ = statement.visit(&mut collector);
+    collector.functions
+}
+
+/// Match a function name against a rule, a trailing '*' matches a prefix
+fn function_matches(rule: &str, name: &str) -> bool {
+    match rule.strip_suffix('*') {
+        Some(prefix) => name
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+        None => rule.eq_ignore_ascii_case(name),
+    }
+}
+
+/// Check function calls against the default denylist and the token's function rules
+/// denied_functions wins over allowed_functions, which lifts the default denylist.
+/// Qualified calls are only allowed for postgate_helpers and pg_catalog.
+fn validate_functions(functions: &[TableRef], rules: &QueryRules) -> Result<(), ParseError> {
+    for function in functions {
+        if let Some(schema) = &function.schema
+            && schema != "postgate_helpers"
+            && !schema.eq_ignore_ascii_case("pg_catalog")
+        {
+            return Err(ParseError::FunctionNotAllowed(format!(
+                "{}.{}",
+                schema, function.name
+            )));
+        }
+
+        let name = function.name.as_str();
+        let matches = |list: &HashSet<String>| list.iter().any(|rule| function_matches(rule, name));
+
+        let denied = matches(&rules.denied_functions)
+            || (DEFAULT_DENIED_FUNCTIONS
+                .iter()
+                .any(|rule| function_matches(rule, name))
+                && !matches(&rules.allowed_functions));
+
+        if denied {
+            return Err(ParseError::FunctionNotAllowed(function.name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Complexity of a statement, measured while walking the AST
+#[derive(Default)]
+struct ComplexityCollector {
+    joins: usize,
+    query_depth: usize,
+    max_query_depth: usize,
+    max_in_list: usize,
+    params: HashSet<String>,
+}
+
+/// Joins of a FROM clause, comma-separated items are cross joins
+fn count_joins<'a>(from: impl IntoIterator<Item = &'a TableWithJoins>) -> usize {
+    let mut items: usize = 0;
+    let mut joins = 0;
+
+    for table in from {
+        items += 1;
+        joins += table.joins.len();
+    }
+
+    joins + items.saturating_sub(1)
+}
+
+impl ComplexityCollector {
+    fn count_select_joins(&mut self, body: &SetExpr) {
+        match body {
+            SetExpr::Select(select) => self.joins += count_joins(&select.from),
+            SetExpr::SetOperation { left, right, .. } => {
+                self.count_select_joins(left);
+                self.count_select_joins(right);
+            }
+            // Nested queries and statements are visited on their own
+            _ => {}
+        }
+    }
+}
+
+impl Visitor for ComplexityCollector {
+    type Break = ();
+
+    fn pre_visit_statement(&mut self, statement: &Statement) -> ControlFlow<()> {
+        match statement {
+            Statement::Update(update) => {
+                let from = match &update.from {
+                    Some(UpdateTableFromKind::BeforeSet(from))
+                    | Some(UpdateTableFromKind::AfterSet(from)) => from.as_slice(),
+                    None => &[],
+                };
+                self.joins += count_joins(std::iter::once(&update.table).chain(from));
+            }
+            Statement::Delete(delete) => {
+                let (FromTable::WithFromKeyword(from) | FromTable::WithoutKeyword(from)) =
+                    &delete.from;
+                self.joins += count_joins(from.iter().chain(delete.using.iter().flatten()));
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        self.query_depth += 1;
+        self.max_query_depth = self.max_query_depth.max(self.query_depth);
+        self.count_select_joins(&query.body);
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, _query: &Query) -> ControlFlow<()> {
+        self.query_depth -= 1;
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<()> {
+        if let Expr::InList { list, .. } = expr {
+            self.max_in_list = self.max_in_list.max(list.len());
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_value(&mut self, value: &Value) -> ControlFlow<()> {
+        if let Value::Placeholder(param) = value {
+            self.params.insert(param.clone());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Whether the statement is built around a query, which is not a subquery itself
+fn has_main_query(statement: &Statement) -> bool {
+    match statement {
+        Statement::Query(_) | Statement::CreateView(_) => true,
+        Statement::Insert(insert) => insert.source.is_some(),
+        Statement::CreateTable(create_table) => create_table.query.is_some(),
+        _ => false,
+    }
+}
+
+fn check_limit(limit: &'static str, value: usize, max: Option<i32>) -> Result<(), ParseError> {
+    match max {
+        Some(max) if value > max.max(0) as usize => {
+            Err(ParseError::LimitExceeded { limit, value, max })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check the statement against the database's complexity limits
+fn validate_complexity(statement: &Statement, limits: &QueryLimits) -> Result<(), ParseError> {
+    let mut collector = ComplexityCollector::default();
+    let _ = statement.visit(&mut collector);
+
+    let subquery_depth = collector
+        .max_query_depth
+        .saturating_sub(has_main_query(statement) as usize);
+
+    check_limit("max_joins", collector.joins, limits.max_joins)?;
+    check_limit(
+        "max_subquery_depth",
+        subquery_depth,
+        limits.max_subquery_depth,
+    )?;
+    check_limit("max_in_list", collector.max_in_list, limits.max_in_list)?;
+    check_limit("max_params", collector.params.len(), limits.max_params)?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn all_operations() -> HashSet<SqlOperation> {
-        HashSet::from([
-            SqlOperation::Select,
-            SqlOperation::Insert,
-            SqlOperation::Update,
-            SqlOperation::Delete,
-        ])
+    fn all_operations() -> QueryRules {
+        QueryRules {
+            allowed_operations: HashSet::from([
+                SqlOperation::Select,
+                SqlOperation::Insert,
+                SqlOperation::Update,
+                SqlOperation::Delete,
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn table_rules(allowed: Option<&[&str]>, denied: &[&str]) -> QueryRules {
+        QueryRules {
+            allowed_tables: allowed.map(|tables| tables.iter().map(|t| t.to_string()).collect()),
+            denied_tables: denied.iter().map(|t| t.to_string()).collect(),
+            ..all_operations()
+        }
     }
 
     #[test]
     fn test_parse_select() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM users WHERE id = $1", &ops);
+        let result = parse_and_validate_with_rules("SELECT * FROM users WHERE id = $1", &ops);
         assert!(result.is_ok());
         let parsed = result.unwrap();
         assert_eq!(parsed.operation, SqlOperation::Select);
@@ -209,72 +749,612 @@ mod tests {
     #[test]
     fn test_parse_insert() {
         let ops = all_operations();
-        let result = parse_and_validate("INSERT INTO users (name, email) VALUES ($1, $2)", &ops);
+        let result =
+            parse_and_validate_with_rules("INSERT INTO users (name, email) VALUES ($1, $2)", &ops);
         assert!(result.is_ok());
         let parsed = result.unwrap();
         assert_eq!(parsed.operation, SqlOperation::Insert);
     }
 
     #[test]
-    fn test_operation_not_allowed() {
+    fn test_parse_with_allowed_operations_only() {
         let ops = HashSet::from([SqlOperation::Select]);
+        assert!(parse_and_validate("SELECT * FROM users", &ops).is_ok());
+
         let result = parse_and_validate("DELETE FROM users WHERE id = $1", &ops);
         assert!(matches!(result, Err(ParseError::OperationNotAllowed(_))));
     }
 
+    #[test]
+    fn test_operation_not_allowed() {
+        let rules = QueryRules {
+            allowed_operations: HashSet::from([SqlOperation::Select]),
+            ..Default::default()
+        };
+        let result = parse_and_validate_with_rules("DELETE FROM users WHERE id = $1", &rules);
+        assert!(matches!(result, Err(ParseError::OperationNotAllowed(_))));
+    }
+
+    #[test]
+    fn test_data_modifying_cte_requires_operation() {
+        let rules = QueryRules {
+            allowed_operations: HashSet::from([SqlOperation::Select]),
+            ..Default::default()
+        };
+
+        let result = parse_and_validate_with_rules(
+            "WITH d AS (DELETE FROM users RETURNING *) SELECT * FROM d",
+            &rules,
+        );
+        assert!(matches!(
+            result,
+            Err(ParseError::OperationNotAllowed(SqlOperation::Delete))
+        ));
+
+        let result = parse_and_validate_with_rules(
+            "WITH u AS (UPDATE users SET name = $1 RETURNING id) SELECT * FROM u",
+            &rules,
+        );
+        assert!(matches!(
+            result,
+            Err(ParseError::OperationNotAllowed(SqlOperation::Update))
+        ));
+
+        // Read-only CTEs stay allowed
+        assert!(
+            parse_and_validate_with_rules(
+                "WITH a AS (SELECT * FROM users) SELECT * FROM a",
+                &rules
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_nested_operations_collected() {
+        let ops = all_operations();
+        let parsed = parse_and_validate_with_rules(
+            "WITH d AS (DELETE FROM users RETURNING *) INSERT INTO archive SELECT * FROM d",
+            &ops,
+        )
+        .unwrap();
+        assert_eq!(parsed.operation, SqlOperation::Insert);
+        assert_eq!(
+            parsed.operations,
+            HashSet::from([SqlOperation::Insert, SqlOperation::Delete])
+        );
+        assert!(!parsed.returns_rows);
+    }
+
+    #[test]
+    fn test_read_only_statements() {
+        let ops = all_operations();
+        let read_only = |sql: &str| {
+            parse_and_validate_with_rules(sql, &ops)
+                .unwrap()
+                .is_read_only()
+        };
+
+        assert!(read_only("SELECT * FROM users"));
+        assert!(read_only("WITH u AS (SELECT * FROM users) SELECT * FROM u"));
+        assert!(!read_only(
+            "WITH d AS (DELETE FROM users RETURNING *) SELECT * FROM d"
+        ));
+        assert!(!read_only(
+            "INSERT INTO users (name) VALUES ('a') RETURNING id"
+        ));
+    }
+
+    #[test]
+    fn test_select_into_requires_create() {
+        let rules = QueryRules {
+            allowed_operations: HashSet::from([SqlOperation::Select]),
+            ..Default::default()
+        };
+        let result = parse_and_validate_with_rules("SELECT * INTO copy FROM users", &rules);
+        assert!(matches!(
+            result,
+            Err(ParseError::OperationNotAllowed(SqlOperation::Create))
+        ));
+
+        // The INTO target is checked like any other table
+        let ops = all_operations();
+        let result =
+            parse_and_validate_with_rules("SELECT * INTO other_schema.copy FROM users", &ops);
+        assert!(matches!(result, Err(ParseError::QualifiedTableName(_))));
+    }
+
+    #[test]
+    fn test_row_locking_requires_update() {
+        let rules = QueryRules {
+            allowed_operations: HashSet::from([SqlOperation::Select]),
+            ..Default::default()
+        };
+
+        for sql in [
+            "SELECT * FROM users FOR UPDATE",
+            "SELECT * FROM users FOR SHARE",
+            "SELECT * FROM (SELECT * FROM users FOR UPDATE) u",
+        ] {
+            let result = parse_and_validate_with_rules(sql, &rules);
+            assert!(matches!(
+                result,
+                Err(ParseError::OperationNotAllowed(SqlOperation::Update))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_explain_requires_permission() {
+        let rules = QueryRules {
+            allowed_operations: HashSet::from([SqlOperation::Select]),
+            ..Default::default()
+        };
+        let result = parse_and_validate_with_rules("EXPLAIN SELECT * FROM users", &rules);
+        assert!(matches!(
+            result,
+            Err(ParseError::OperationNotAllowed(SqlOperation::Explain))
+        ));
+
+        // The explained statement needs its own permission too
+        let rules = QueryRules {
+            allowed_operations: HashSet::from([SqlOperation::Select, SqlOperation::Explain]),
+            ..Default::default()
+        };
+        let parsed =
+            parse_and_validate_with_rules("EXPLAIN (FORMAT JSON) SELECT * FROM users", &rules)
+                .unwrap();
+        assert_eq!(parsed.operation, SqlOperation::Explain);
+        assert!(parsed.tables.contains("users"));
+        assert!(parsed.returns_rows);
+
+        let result =
+            parse_and_validate_with_rules("EXPLAIN DELETE FROM users WHERE id = $1", &rules);
+        assert!(matches!(
+            result,
+            Err(ParseError::OperationNotAllowed(SqlOperation::Delete))
+        ));
+
+        let result =
+            parse_and_validate_with_rules("EXPLAIN SELECT * FROM pg_catalog.pg_roles", &rules);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_explain_analyze_rejected() {
+        let mut rules = all_operations();
+        rules.allowed_operations.insert(SqlOperation::Explain);
+
+        for sql in [
+            "EXPLAIN ANALYZE SELECT * FROM users",
+            "EXPLAIN (ANALYZE) SELECT * FROM users",
+            "EXPLAIN (ANALYZE true, BUFFERS) SELECT * FROM users",
+            "EXPLAIN (FORMAT JSON, ANALYZE on) DELETE FROM users",
+            "EXPLAIN (ANALYSE) SELECT * FROM users",
+            "EXPLAIN (analyse true) DELETE FROM users",
+        ] {
+            let result = parse_and_validate_with_rules(sql, &rules);
+            assert!(matches!(result, Err(ParseError::ExplainAnalyze)), "{sql}");
+        }
+
+        // Not understood by the parser without parentheses, rejected as well
+        assert!(
+            parse_and_validate_with_rules("EXPLAIN ANALYSE SELECT * FROM users", &rules).is_err()
+        );
+
+        assert!(
+            parse_and_validate_with_rules("EXPLAIN (ANALYZE false) SELECT * FROM users", &rules)
+                .is_ok()
+        );
+        assert!(
+            parse_and_validate_with_rules("EXPLAIN (ANALYZE off) SELECT * FROM users", &rules)
+                .is_ok()
+        );
+    }
+
     #[test]
     fn test_multiple_statements_rejected() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT 1; SELECT 2", &ops);
+        let result = parse_and_validate_with_rules("SELECT 1; SELECT 2", &ops);
         assert!(matches!(result, Err(ParseError::MultipleStatements)));
     }
 
     #[test]
     fn test_qualified_table_name_rejected() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM public.users", &ops);
+        let result = parse_and_validate_with_rules("SELECT * FROM public.users", &ops);
         assert!(matches!(result, Err(ParseError::QualifiedTableName(_))));
     }
 
     #[test]
     fn test_schema_qualified_rejected() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM other_schema.secrets", &ops);
+        let result = parse_and_validate_with_rules("SELECT * FROM other_schema.secrets", &ops);
         assert!(matches!(result, Err(ParseError::QualifiedTableName(_))));
     }
 
     #[test]
     fn test_pg_catalog_rejected() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM pg_catalog.pg_tables", &ops);
+        let result = parse_and_validate_with_rules("SELECT * FROM pg_catalog.pg_tables", &ops);
         assert!(matches!(result, Err(ParseError::QualifiedTableName(_))));
     }
 
     #[test]
     fn test_pg_tables_rejected() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM pg_tables", &ops);
+        let result = parse_and_validate_with_rules("SELECT * FROM pg_tables", &ops);
         assert!(matches!(result, Err(ParseError::SystemTableAccess(_))));
     }
 
     #[test]
     fn test_pg_namespace_rejected() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM pg_namespace", &ops);
+        let result = parse_and_validate_with_rules("SELECT * FROM pg_namespace", &ops);
         assert!(matches!(result, Err(ParseError::SystemTableAccess(_))));
     }
 
     #[test]
     fn test_information_schema_rejected() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM information_schema.tables", &ops);
+        let result = parse_and_validate_with_rules("SELECT * FROM information_schema.tables", &ops);
+        assert!(matches!(result, Err(ParseError::QualifiedTableName(_))));
+    }
+
+    #[test]
+    fn test_drop_qualified_table_rejected() {
+        let ops = all_operations();
+        let result = parse_and_validate_with_rules("DROP TABLE other_schema.secrets", &ops);
         assert!(matches!(result, Err(ParseError::QualifiedTableName(_))));
     }
 
     #[test]
     fn test_postgate_helpers_allowed() {
         let ops = all_operations();
-        let result = parse_and_validate("SELECT * FROM postgate_helpers.list_tables()", &ops);
+        let result =
+            parse_and_validate_with_rules("SELECT * FROM postgate_helpers.list_tables()", &ops);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_dangerous_functions_denied_by_default() {
+        let ops = all_operations();
+
+        for sql in [
+            "SELECT pg_sleep(10)",
+            "SELECT PG_SLEEP_FOR('1 minute')",
+            "SELECT set_config('search_path', 'other_schema', false)",
+            "SELECT pg_read_file('/etc/passwd')",
+            "SELECT lo_import('/etc/passwd')",
+            "SELECT * FROM dblink('host=evil', 'SELECT 1') AS t(x int)",
+            "SELECT pg_terminate_backend(pid) FROM users",
+            "SELECT query_to_xml('SELECT * FROM pg_authid', true, true, '')",
+            "SELECT * FROM users WHERE id = (SELECT pg_sleep(1))",
+            "INSERT INTO users (name) VALUES (current_setting('data_directory'))",
+            "SELECT pg_catalog.pg_sleep(1)",
+            "SELECT word FROM ts_stat('SELECT to_tsvector(x) FROM secret')",
+            "SELECT ts_stat('select pg_sleep(100)')",
+            "SELECT ts_rewrite(to_tsquery('a'), 'SELECT t, s FROM secret')",
+            "SELECT (pg_stat_get_activity(NULL)).query",
+            "SELECT pg_stat_get_backend_activity(1)",
+            "SELECT pg_stat_get_backend_userid(1)",
+        ] {
+            let result = parse_and_validate_with_rules(sql, &ops);
+            assert!(
+                matches!(result, Err(ParseError::FunctionNotAllowed(_))),
+                "{sql}: {result:?}"
+            );
+        }
+
+        // Everyday functions are fine
+        assert!(
+            parse_and_validate_with_rules("SELECT count(*), now(), lower(name) FROM users", &ops)
+                .is_ok()
+        );
+        assert!(
+            parse_and_validate_with_rules("SELECT * FROM generate_series(1, 10)", &ops).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_qualified_function_rejected() {
+        let ops = all_operations();
+        let result = parse_and_validate_with_rules("SELECT other_schema.secret_fn()", &ops);
+        assert!(
+            matches!(result, Err(ParseError::FunctionNotAllowed(f)) if f == "other_schema.secret_fn")
+        );
+
+        assert!(
+            parse_and_validate_with_rules("SELECT postgate_helpers.describe_table('users')", &ops)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_function_rules() {
+        let rules = QueryRules {
+            allowed_functions: HashSet::from(["pg_sleep".to_string()]),
+            denied_functions: HashSet::from(["random".to_string(), "md5*".to_string()]),
+            ..all_operations()
+        };
+
+        assert!(parse_and_validate_with_rules("SELECT pg_sleep(1)", &rules).is_ok());
+
+        // Only the listed function is lifted from the default denylist
+        let result = parse_and_validate_with_rules("SELECT pg_sleep_for('1 second')", &rules);
+        assert!(matches!(result, Err(ParseError::FunctionNotAllowed(_))));
+
+        let result = parse_and_validate_with_rules("SELECT RANDOM()", &rules);
+        assert!(matches!(result, Err(ParseError::FunctionNotAllowed(f)) if f == "RANDOM"));
+
+        let result = parse_and_validate_with_rules("SELECT md5(name) FROM users", &rules);
+        assert!(matches!(result, Err(ParseError::FunctionNotAllowed(_))));
+
+        // denied_functions wins over allowed_functions
+        let rules = QueryRules {
+            allowed_functions: HashSet::from(["pg_sleep".to_string()]),
+            denied_functions: HashSet::from(["pg_sleep".to_string()]),
+            ..all_operations()
+        };
+        let result = parse_and_validate_with_rules("SELECT pg_sleep(1)", &rules);
+        assert!(matches!(result, Err(ParseError::FunctionNotAllowed(_))));
+    }
+
+    fn limits(limits: QueryLimits) -> QueryRules {
+        QueryRules {
+            limits,
+            ..all_operations()
+        }
+    }
+
+    fn assert_limit_exceeded(sql: &str, rules: &QueryRules, expected: &str) {
+        let result = parse_and_validate_with_rules(sql, rules);
+        assert!(
+            matches!(&result, Err(ParseError::LimitExceeded { limit, .. }) if *limit == expected),
+            "{sql}: {:?}",
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_max_joins() {
+        let rules = limits(QueryLimits {
+            max_joins: Some(2),
+            ..Default::default()
+        });
+
+        assert!(
+            parse_and_validate_with_rules("SELECT * FROM a JOIN b ON a.id = b.id, c", &rules)
+                .is_ok()
+        );
+        assert_limit_exceeded("SELECT * FROM a, b, c, d", &rules, "max_joins");
+        assert_limit_exceeded(
+            "SELECT * FROM a JOIN b ON true JOIN c ON true LEFT JOIN d ON true",
+            &rules,
+            "max_joins",
+        );
+
+        // Joins add up across subqueries
+        assert_limit_exceeded(
+            "SELECT * FROM a, b, c WHERE a.id IN (SELECT d.id FROM d, e)",
+            &rules,
+            "max_joins",
+        );
+        assert_limit_exceeded(
+            "UPDATE a SET x = 1 FROM b, c, d WHERE a.id = b.id",
+            &rules,
+            "max_joins",
+        );
+        assert_limit_exceeded("DELETE FROM a USING b, c, d", &rules, "max_joins");
+    }
+
+    #[test]
+    fn test_max_subquery_depth() {
+        let rules = limits(QueryLimits {
+            max_subquery_depth: Some(1),
+            ..Default::default()
+        });
+
+        assert!(
+            parse_and_validate_with_rules("SELECT * FROM a WHERE id IN (SELECT id FROM b)", &rules)
+                .is_ok()
+        );
+        assert!(
+            parse_and_validate_with_rules(
+                "INSERT INTO a SELECT * FROM (SELECT * FROM b) t",
+                &rules
+            )
+            .is_ok()
+        );
+        assert!(
+            parse_and_validate_with_rules("DELETE FROM a WHERE id IN (SELECT id FROM b)", &rules)
+                .is_ok()
+        );
+
+        assert_limit_exceeded(
+            "SELECT * FROM a WHERE id IN (SELECT id FROM b WHERE x IN (SELECT x FROM c))",
+            &rules,
+            "max_subquery_depth",
+        );
+        assert_limit_exceeded(
+            "WITH t AS (SELECT * FROM (SELECT * FROM a) s) SELECT * FROM t",
+            &rules,
+            "max_subquery_depth",
+        );
+    }
+
+    #[test]
+    fn test_max_in_list_and_params() {
+        let rules = limits(QueryLimits {
+            max_in_list: Some(3),
+            max_params: Some(2),
+            ..Default::default()
+        });
+
+        assert!(
+            parse_and_validate_with_rules("SELECT * FROM a WHERE id IN (1, 2, 3)", &rules).is_ok()
+        );
+        assert_limit_exceeded(
+            "SELECT * FROM a WHERE id IN (1, 2, 3, 4)",
+            &rules,
+            "max_in_list",
+        );
+
+        // Parameters are counted once, however often they are used
+        assert!(
+            parse_and_validate_with_rules(
+                "SELECT * FROM a WHERE x = $1 OR y = $1 OR z = $2",
+                &rules
+            )
+            .is_ok()
+        );
+        assert_limit_exceeded("INSERT INTO a VALUES ($1, $2, $3)", &rules, "max_params");
+    }
+
+    #[test]
+    fn test_max_sql_length() {
+        let rules = limits(QueryLimits {
+            max_sql_length: Some(20),
+            ..Default::default()
+        });
+
+        assert!(parse_and_validate_with_rules("SELECT * FROM a", &rules).is_ok());
+        assert_limit_exceeded("SELECT * FROM a WHERE id = 1", &rules, "max_sql_length");
+
+        // Checked before parsing
+        assert_limit_exceeded("not even valid sql at all", &rules, "max_sql_length");
+    }
+
+    #[test]
+    fn test_safe_updates() {
+        let rules = QueryRules {
+            safe_updates: true,
+            ..all_operations()
+        };
+
+        assert!(
+            parse_and_validate_with_rules("UPDATE users SET name = $1 WHERE id = $2", &rules)
+                .is_ok()
+        );
+        assert!(
+            parse_and_validate_with_rules("DELETE FROM users WHERE id = $1 AND 1 = 1", &rules)
+                .is_ok()
+        );
+        assert!(
+            parse_and_validate_with_rules(
+                "DELETE FROM users WHERE id IN (SELECT user_id FROM bans)",
+                &rules
+            )
+            .is_ok()
+        );
+        // Subqueries depend on the data, even without a column of the updated table
+        for sql in [
+            "DELETE FROM users WHERE EXISTS (SELECT 1 FROM bans)",
+            "UPDATE users SET name = $1 WHERE (SELECT count(*) FROM bans) > 0",
+            "DELETE FROM users WHERE 1 IN (SELECT 1 FROM bans)",
+        ] {
+            assert!(parse_and_validate_with_rules(sql, &rules).is_ok(), "{sql}");
+        }
+
+        for sql in [
+            "UPDATE users SET name = $1",
+            "DELETE FROM users",
+            "DELETE FROM users WHERE 1 = 1",
+            "DELETE FROM users WHERE true",
+            "DELETE FROM users WHERE (('a' = 'a'))",
+            "UPDATE users SET name = $1 WHERE id = id",
+            "UPDATE users SET name = $1 WHERE id = $2 OR 1 = 1",
+            "DELETE FROM users WHERE $1",
+            "WITH d AS (DELETE FROM users RETURNING *) SELECT * FROM d",
+        ] {
+            let result = parse_and_validate_with_rules(sql, &rules);
+            assert!(
+                matches!(result, Err(ParseError::UnfilteredWrite(_))),
+                "{sql}"
+            );
+        }
+
+        // Tokens without safe_updates are unaffected
+        assert!(parse_and_validate_with_rules("DELETE FROM users", &all_operations()).is_ok());
+    }
+
+    #[test]
+    fn test_allowed_tables() {
+        let rules = table_rules(Some(&["events"]), &[]);
+
+        assert!(parse_and_validate_with_rules("SELECT * FROM events", &rules).is_ok());
+        assert!(
+            parse_and_validate_with_rules("INSERT INTO Events (name) VALUES ($1)", &rules).is_ok()
+        );
+        assert!(parse_and_validate_with_rules("SELECT * FROM \"events\"", &rules).is_ok());
+
+        // A quoted name is case-sensitive: "EVENTS" is another table
+        let result = parse_and_validate_with_rules("SELECT * FROM \"EVENTS\"", &rules);
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(t)) if t == "EVENTS"));
+
+        let result = parse_and_validate_with_rules("SELECT * FROM users", &rules);
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(t)) if t == "users"));
+
+        // Every referenced table must be allowed, wherever it appears
+        let result = parse_and_validate_with_rules(
+            "SELECT * FROM events WHERE user_id IN (SELECT id FROM users)",
+            &rules,
+        );
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(_))));
+
+        let result = parse_and_validate_with_rules(
+            "UPDATE events SET name = u.name FROM users u WHERE u.id = events.user_id",
+            &rules,
+        );
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(_))));
+    }
+
+    #[test]
+    fn test_denied_tables() {
+        let rules = table_rules(None, &["secrets"]);
+
+        assert!(parse_and_validate_with_rules("SELECT * FROM users", &rules).is_ok());
+
+        let result = parse_and_validate_with_rules("DELETE FROM SECRETS", &rules);
+        assert!(matches!(result, Err(ParseError::TableDenied(_))));
+
+        let result = parse_and_validate_with_rules(
+            "SELECT * FROM users JOIN secrets ON secrets.user_id = users.id",
+            &rules,
+        );
+        assert!(matches!(result, Err(ParseError::TableDenied(_))));
+    }
+
+    #[test]
+    fn test_denied_tables_win_over_allowed() {
+        let rules = table_rules(Some(&["events"]), &["events"]);
+        let result = parse_and_validate_with_rules("SELECT * FROM events", &rules);
+        assert!(matches!(result, Err(ParseError::TableDenied(_))));
+    }
+
+    #[test]
+    fn test_table_rules_apply_to_ddl() {
+        let rules = QueryRules {
+            allowed_operations: HashSet::new(),
+            ..table_rules(Some(&["events"]), &[])
+        };
+
+        assert!(parse_and_validate_with_rules("CREATE TABLE events (id int)", &rules).is_ok());
+
+        let result = parse_and_validate_with_rules("CREATE TABLE users (id int)", &rules);
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(_))));
+
+        let result = parse_and_validate_with_rules("ALTER TABLE users ADD COLUMN x int", &rules);
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(_))));
+
+        let result = parse_and_validate_with_rules("DROP TABLE users", &rules);
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(_))));
+
+        let result =
+            parse_and_validate_with_rules("CREATE VIEW users AS SELECT * FROM events", &rules);
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(_))));
+
+        let result = parse_and_validate_with_rules("TRUNCATE users", &rules);
+        assert!(matches!(result, Err(ParseError::TableNotAllowed(_))));
+    }
 }
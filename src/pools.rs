@@ -0,0 +1,289 @@
+//! Connection pools of dedicated backends
+//!
+//! A pool is created on first use of a database and kept for later requests.
+//! It is closed once idle for longer than [`POOL_IDLE_TIMEOUT`], when the
+//! database is deleted (through the admin API, or found gone from
+//! `postgate_databases` after a `delete_tenant_database` call), and rebuilt when
+//! its connection string or `max_connections` changes. At most
+//! [`MAX_DEDICATED_POOLS`] are kept open, the least recently used one is
+//! dropped to make room for a new one.
+
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Pools not used for this long are closed
+pub const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Dedicated pools kept open at the same time
+pub const MAX_DEDICATED_POOLS: usize = 100;
+
+/// Connections per pool for databases without max_connections
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
+/// How often idle pools and pools of deleted databases are looked for
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Entry {
+    pool: PgPool,
+    connection_string: String,
+    max_connections: u32,
+    last_used: Instant,
+}
+
+/// Open dedicated pools by database id
+#[derive(Default)]
+pub(crate) struct DedicatedPools {
+    pools: std::sync::Mutex<HashMap<Uuid, Entry>>,
+}
+
+impl DedicatedPools {
+    /// Create the registry and start closing idle pools and pools of deleted databases
+    /// (looked up in `shared_pool`) in the background. The task stops once the registry
+    /// is dropped.
+    pub fn start(shared_pool: PgPool) -> Arc<Self> {
+        let registry = Arc::new(Self::default());
+
+        tokio::spawn(reap_pools(Arc::downgrade(&registry), shared_pool));
+
+        registry
+    }
+
+    /// Pool of a database, connecting a new one if there is none for this
+    /// connection string and size yet
+    pub async fn get_or_create(
+        &self,
+        database_id: Uuid,
+        connection_string: &str,
+        max_connections: u32,
+    ) -> Result<PgPool, sqlx::Error> {
+        if let Some(pool) = self.get(database_id, connection_string, max_connections) {
+            return Ok(pool);
+        }
+
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(connection_string)
+            .await?;
+
+        Ok(self.keep(
+            database_id,
+            Entry {
+                pool,
+                connection_string: connection_string.to_string(),
+                max_connections,
+                last_used: Instant::now(),
+            },
+        ))
+    }
+
+    /// Register a newly connected pool and return the one to use
+    fn keep(&self, database_id: Uuid, entry: Entry) -> PgPool {
+        let mut pools = self.pools.lock().expect("dedicated pools poisoned");
+
+        // Another request may have connected the same pool meanwhile. Closing it would
+        // fail that request, so it is kept and the new one closed instead.
+        if let Some(existing) = pools.get_mut(&database_id)
+            && existing.connection_string == entry.connection_string
+            && existing.max_connections == entry.max_connections
+        {
+            existing.last_used = Instant::now();
+            let pool = existing.pool.clone();
+            close(entry);
+            return pool;
+        }
+
+        if !pools.contains_key(&database_id) && pools.len() >= MAX_DEDICATED_POOLS {
+            // Pools without checked-out connections go first. A busy one isn't closed,
+            // which would fail its requests: it is dropped once they release it.
+            let least_recent = pools
+                .iter()
+                .min_by_key(|(_, entry)| (!is_unused(&entry.pool), entry.last_used))
+                .map(|(id, _)| *id);
+            if let Some(entry) = least_recent.and_then(|id| pools.remove(&id))
+                && is_unused(&entry.pool)
+            {
+                close(entry);
+            }
+        }
+
+        let pool = entry.pool.clone();
+        if let Some(replaced) = pools.insert(database_id, entry) {
+            close(replaced);
+        }
+
+        pool
+    }
+
+    /// Close the pool of a database, e.g. once it is deleted
+    pub fn remove(&self, database_id: Uuid) {
+        let entry = self
+            .pools
+            .lock()
+            .expect("dedicated pools poisoned")
+            .remove(&database_id);
+
+        if let Some(entry) = entry {
+            close(entry);
+        }
+    }
+
+    /// Cached pool, if it was built with the same settings.
+    /// A stale one (the database row changed) is closed.
+    fn get(
+        &self,
+        database_id: Uuid,
+        connection_string: &str,
+        max_connections: u32,
+    ) -> Option<PgPool> {
+        let mut pools = self.pools.lock().expect("dedicated pools poisoned");
+
+        let entry = pools.get_mut(&database_id)?;
+        if entry.connection_string == connection_string && entry.max_connections == max_connections
+        {
+            entry.last_used = Instant::now();
+            return Some(entry.pool.clone());
+        }
+
+        if let Some(entry) = pools.remove(&database_id) {
+            close(entry);
+        }
+        None
+    }
+
+    /// Databases with an open pool
+    fn database_ids(&self) -> Vec<Uuid> {
+        let pools = self.pools.lock().expect("dedicated pools poisoned");
+
+        pools.keys().copied().collect()
+    }
+
+    /// Close pools unused for longer than the timeout
+    fn remove_idle(&self) {
+        let mut pools = self.pools.lock().expect("dedicated pools poisoned");
+
+        let idle: Vec<Uuid> = pools
+            .iter()
+            .filter(|(_, entry)| entry.last_used.elapsed() >= POOL_IDLE_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in idle {
+            if let Some(entry) = pools.remove(&id) {
+                close(entry);
+            }
+        }
+    }
+}
+
+/// No connection of the pool is checked out
+fn is_unused(pool: &PgPool) -> bool {
+    pool.size() as usize == pool.num_idle()
+}
+
+/// Close a pool in the background. Connections in use are closed once released.
+fn close(entry: Entry) {
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        runtime.spawn(async move { entry.pool.close().await });
+    }
+}
+
+async fn reap_pools(registry: Weak<DedicatedPools>, shared_pool: PgPool) {
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let Some(registry) = registry.upgrade() else {
+            return;
+        };
+        registry.remove_idle();
+
+        // Databases can also be deleted with SQL (delete_tenant_database), which
+        // doesn't go through the registry
+        let ids = registry.database_ids();
+        if ids.is_empty() {
+            continue;
+        }
+
+        let existing =
+            sqlx::query_scalar::<_, Uuid>("SELECT id FROM postgate_databases WHERE id = ANY($1)")
+                .bind(&ids)
+                .fetch_all(&shared_pool)
+                .await;
+
+        match existing {
+            Ok(existing) => ids
+                .into_iter()
+                .filter(|id| !existing.contains(id))
+                .for_each(|id| registry.remove(id)),
+            Err(e) => log::warn!("Failed to look up databases of dedicated pools: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(connection_string: &str) -> Entry {
+        Entry {
+            pool: PgPoolOptions::new()
+                .connect_lazy(connection_string)
+                .unwrap(),
+            connection_string: connection_string.to_string(),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            last_used: Instant::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn racing_pool_is_closed_instead_of_the_kept_one() {
+        let pools = DedicatedPools::default();
+        let id = Uuid::new_v4();
+
+        let first = pools.keep(id, entry("postgres://localhost/a"));
+
+        let racing = entry("postgres://localhost/a");
+        let racing_pool = racing.pool.clone();
+        let kept = pools.keep(id, racing);
+
+        tokio::task::yield_now().await;
+        assert!(!first.is_closed());
+        assert!(!kept.is_closed());
+        assert!(racing_pool.is_closed());
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_pool_is_dropped_when_full() {
+        let pools = DedicatedPools::default();
+
+        let ids: Vec<Uuid> = (0..MAX_DEDICATED_POOLS).map(|_| Uuid::new_v4()).collect();
+        let oldest = pools.keep(ids[0], entry("postgres://localhost/a"));
+        for id in &ids[1..] {
+            pools.keep(*id, entry("postgres://localhost/a"));
+        }
+
+        pools.keep(Uuid::new_v4(), entry("postgres://localhost/a"));
+
+        assert_eq!(pools.database_ids().len(), MAX_DEDICATED_POOLS);
+        assert!(!pools.database_ids().contains(&ids[0]));
+        let closed = tokio::time::timeout(Duration::from_secs(1), oldest.close_event()).await;
+        assert!(closed.is_ok());
+    }
+
+    #[tokio::test]
+    async fn changed_settings_replace_the_pool() {
+        let pools = DedicatedPools::default();
+        let id = Uuid::new_v4();
+
+        let old = pools.keep(id, entry("postgres://localhost/a"));
+        let new = pools.keep(id, entry("postgres://localhost/b"));
+
+        tokio::task::yield_now().await;
+        assert!(old.is_closed());
+        assert!(!new.is_closed());
+    }
+}
@@ -1,11 +1,17 @@
 use actix_web::{HttpRequest, HttpResponse, web};
+use std::convert::Infallible;
+use std::time::Duration;
 
-use crate::auth::{compute_token_hash, extract_token};
-use crate::config::Config;
+use crate::auth::{TokenInfo, compute_token_hash, extract_token};
+use crate::config::{Config, DatabaseConfig, SqlOperation};
+use crate::cursor::{CloseCursorRequest, FetchCursorRequest, OpenCursorResponse};
 use crate::error::PostgateError;
-use crate::executor::{ExecutorPool, QueryRequest, QueryResponse};
-use crate::parser::parse_and_validate;
-use crate::store::Store;
+use crate::executor::{
+    ColumnarResponse, ExecuteOptions, ExecutorError, ExecutorPool, QueryRequest, QueryResponse,
+    RowStream, StreamItem, TransactionRequest, TransactionResponse, TransactionStatement,
+};
+use crate::parser::{ParseError, parse_and_validate_with_rules};
+use crate::store::{Store, StoreError};
 
 pub struct AppState {
     pub config: Config,
@@ -23,14 +29,77 @@ impl AppState {
     }
 }
 
-/// Default query timeout in seconds
-const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+/// Query timeout for databases without statement_timeout_ms
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 
-pub async fn query_handler(
-    req: HttpRequest,
-    state: web::Data<AppState>,
-    body: web::Json<QueryRequest>,
-) -> Result<HttpResponse, PostgateError> {
+/// Wait for a concurrency slot for databases without queue_timeout_ms
+const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Header a client can use to ask for a shorter timeout, in milliseconds
+const TIMEOUT_HEADER: &str = "X-Postgate-Timeout";
+
+/// Header choosing what happens past max_rows: `error` (default) or `truncate`
+const ROW_LIMIT_HEADER: &str = "X-Postgate-Row-Limit";
+
+/// Response header set when rows were dropped in truncate mode
+const TRUNCATED_HEADER: &str = "X-Postgate-Truncated";
+
+/// Result formats a client can ask for with the Accept header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    /// Single JSON document with all rows (default)
+    Json,
+    /// Column metadata once, then rows as arrays in column order
+    Columnar,
+    /// One JSON object per line, streamed as rows arrive
+    Ndjson,
+    /// Header row then one CSV record per row
+    Csv,
+    /// Apache Arrow IPC stream
+    #[cfg(feature = "arrow")]
+    Arrow,
+}
+
+impl ResponseFormat {
+    /// Pick the first supported media type listed in the Accept header
+    fn from_request(req: &HttpRequest) -> Self {
+        let accept = req
+            .headers()
+            .get("Accept")
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default();
+
+        accept
+            .split(',')
+            .filter_map(|media_type| match media_type.split(';').next()?.trim() {
+                "application/vnd.postgate.columnar+json" => Some(ResponseFormat::Columnar),
+                "application/x-ndjson" => Some(ResponseFormat::Ndjson),
+                "text/csv" => Some(ResponseFormat::Csv),
+                #[cfg(feature = "arrow")]
+                crate::arrow::ARROW_STREAM_CONTENT_TYPE => Some(ResponseFormat::Arrow),
+                "application/json" => Some(ResponseFormat::Json),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(ResponseFormat::Json)
+    }
+
+    /// Formats that report column types even when no row is returned
+    fn needs_column_metadata(self) -> bool {
+        match self {
+            ResponseFormat::Json | ResponseFormat::Ndjson => false,
+            ResponseFormat::Columnar | ResponseFormat::Csv => true,
+            #[cfg(feature = "arrow")]
+            ResponseFormat::Arrow => true,
+        }
+    }
+}
+
+/// Validate the request token
+pub(crate) async fn authenticate_token(
+    req: &HttpRequest,
+    state: &AppState,
+) -> Result<TokenInfo, PostgateError> {
     // Extract and validate token
     let auth_header = req
         .headers()
@@ -48,7 +117,20 @@ pub async fn query_handler(
         .store
         .validate_token(&token_hash)
         .await
-        .map_err(|_| PostgateError::InvalidAuth)?;
+        .map_err(|e| match e {
+            StoreError::TokenExpired => PostgateError::TokenExpired,
+            _ => PostgateError::InvalidAuth,
+        })?;
+
+    Ok(token_info)
+}
+
+/// Validate the request token and load the database it grants access to
+async fn authenticate(
+    req: &HttpRequest,
+    state: &AppState,
+) -> Result<(TokenInfo, DatabaseConfig), PostgateError> {
+    let mut token_info = authenticate_token(req, state).await?;
 
     // Load database config from store
     let db_config = state
@@ -57,19 +139,212 @@ pub async fn query_handler(
         .await
         .map_err(|_| PostgateError::DatabaseNotFound(token_info.database_id))?;
 
-    // Parse and validate SQL using allowed_operations from token
-    let parsed = parse_and_validate(&body.sql, &token_info.allowed_operations)?;
+    // Complexity limits are set per database, validated with the token's rules
+    token_info.rules.limits = db_config.limits.clone();
+
+    Ok((token_info, db_config))
+}
 
-    // Execute query with max_rows from database config
-    let response: QueryResponse = state
+/// Query timeout for a request: the database ceiling (or server default),
+/// lowered by the X-Postgate-Timeout header when present
+fn query_timeout(req: &HttpRequest, db_config: &DatabaseConfig) -> Result<Duration, PostgateError> {
+    let ceiling = db_config
+        .statement_timeout_ms
+        .map(|ms| Duration::from_millis(ms as u64))
+        .unwrap_or(DEFAULT_TIMEOUT);
+
+    let Some(header) = req.headers().get(TIMEOUT_HEADER) else {
+        return Ok(ceiling);
+    };
+
+    let requested = header
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+        .ok_or_else(|| {
+            PostgateError::BadRequest(format!(
+                "{} must be a positive number of milliseconds",
+                TIMEOUT_HEADER
+            ))
+        })?;
+
+    Ok(ceiling.min(Duration::from_millis(requested)))
+}
+
+/// True if the client asked for results over max_rows to be truncated
+fn truncate_rows(req: &HttpRequest) -> Result<bool, PostgateError> {
+    let Some(header) = req.headers().get(ROW_LIMIT_HEADER) else {
+        return Ok(false);
+    };
+
+    match header.to_str().map(str::trim) {
+        Ok(mode) if mode.eq_ignore_ascii_case("error") => Ok(false),
+        Ok(mode) if mode.eq_ignore_ascii_case("truncate") => Ok(true),
+        _ => Err(PostgateError::BadRequest(format!(
+            "{} must be 'error' or 'truncate'",
+            ROW_LIMIT_HEADER
+        ))),
+    }
+}
+
+fn execute_options(
+    req: &HttpRequest,
+    db_config: &DatabaseConfig,
+    describe_columns: bool,
+) -> Result<ExecuteOptions, PostgateError> {
+    Ok(ExecuteOptions {
+        max_rows: db_config.max_rows as u32,
+        timeout: query_timeout(req, db_config)?,
+        describe_columns,
+        max_plan_cost: db_config.max_plan_cost,
+        truncate: truncate_rows(req)?,
+        max_concurrent_queries: db_config.max_concurrent_queries.map(|max| max as u32),
+        queue_timeout: db_config
+            .queue_timeout_ms
+            .map(|ms| Duration::from_millis(ms as u64))
+            .unwrap_or(DEFAULT_QUEUE_TIMEOUT),
+        max_connections: db_config.max_connections.map(|max| max as u32),
+    })
+}
+
+pub async fn query_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<QueryRequest>,
+) -> Result<HttpResponse, PostgateError> {
+    let (token_info, db_config) = authenticate(&req, &state).await?;
+
+    // Parse and validate SQL using the token's rules
+    let parsed = parse_and_validate_with_rules(&body.sql, &token_info.rules)?;
+
+    let format = ResponseFormat::from_request(&req);
+
+    // Execute query with max_rows and timeout from database config
+    let options = execute_options(&req, &db_config, format.needs_column_metadata())?;
+
+    if parsed.returns_rows && format == ResponseFormat::Ndjson {
+        let rows = state
+            .executor_pool
+            .execute_stream(
+                token_info.database_id,
+                &db_config.backend,
+                body.into_inner(),
+                &parsed,
+                options,
+            )
+            .await
+            .map_err(PostgateError::Executor)?;
+
+        return ndjson_response(rows).await;
+    }
+
+    let result = state
         .executor_pool
         .execute(
             token_info.database_id,
             &db_config.backend,
             &body,
-            db_config.max_rows as u32,
-            DEFAULT_TIMEOUT_SECONDS,
-            parsed.operation.is_ddl(),
+            &parsed,
+            options,
+        )
+        .await
+        .map_err(PostgateError::Executor)?;
+
+    // CSV and Arrow have no field for it, so every format gets the header
+    let mut response = HttpResponse::Ok();
+    if result.truncated {
+        response.insert_header((TRUNCATED_HEADER, "true"));
+    }
+
+    match format {
+        ResponseFormat::Columnar => Ok(response
+            .content_type("application/vnd.postgate.columnar+json")
+            .json(ColumnarResponse::try_from(result)?)),
+        ResponseFormat::Csv => Ok(response
+            .content_type(crate::csv::CSV_CONTENT_TYPE)
+            .body(crate::csv::encode_csv(result)?)),
+        #[cfg(feature = "arrow")]
+        ResponseFormat::Arrow => {
+            let body = crate::arrow::encode_ipc_stream(&result).map_err(|e| match e {
+                crate::arrow::EncodeError::Decode(e) => PostgateError::Executor(e),
+                crate::arrow::EncodeError::Arrow(e) => PostgateError::Internal(e.to_string()),
+            })?;
+
+            Ok(response
+                .content_type(crate::arrow::ARROW_STREAM_CONTENT_TYPE)
+                .body(body))
+        }
+        _ => Ok(response.json(QueryResponse::try_from(result)?)),
+    }
+}
+
+/// Stream rows as newline-delimited JSON.
+/// Errors raised before the first row get a regular error response; later errors
+/// (e.g. row limit exceeded) are sent as a final `{"error", "code"}` line, and
+/// truncation as a final `{"truncated": true}` line.
+async fn ndjson_response(mut rows: RowStream) -> Result<HttpResponse, PostgateError> {
+    let first = match rows.recv().await {
+        Some(Err(e)) => return Err(PostgateError::Executor(e)),
+        first => first,
+    };
+
+    let body = futures_util::stream::unfold((first, rows), |(pending, mut rows)| async move {
+        let item = match pending {
+            Some(item) => item,
+            None => rows.recv().await?,
+        };
+
+        Some((Ok::<_, Infallible>(ndjson_line(item)), (None, rows)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(body))
+}
+
+fn ndjson_line(item: Result<StreamItem, ExecutorError>) -> web::Bytes {
+    let mut line = match item {
+        Ok(StreamItem::Row(row)) => serde_json::to_vec(&row),
+        Ok(StreamItem::Truncated) => serde_json::to_vec(&serde_json::json!({"truncated": true})),
+        Err(e) => serde_json::to_vec(&PostgateError::Executor(e).to_error_response()),
+    }
+    .unwrap_or_default();
+
+    line.push(b'\n');
+    web::Bytes::from(line)
+}
+
+pub async fn transaction_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<TransactionRequest>,
+) -> Result<HttpResponse, PostgateError> {
+    let (token_info, db_config) = authenticate(&req, &state).await?;
+
+    if body.queries.is_empty() {
+        return Err(ParseError::EmptyQuery.into());
+    }
+
+    // Validate every statement before anything is sent to the database
+    let mut statements = Vec::with_capacity(body.queries.len());
+    for query in &body.queries {
+        let parsed = parse_and_validate_with_rules(&query.sql, &token_info.rules)?;
+
+        statements.push(TransactionStatement {
+            request: query,
+            operation: parsed.operation,
+            returns_rows: parsed.returns_rows,
+        });
+    }
+
+    let response: TransactionResponse = state
+        .executor_pool
+        .execute_transaction(
+            token_info.database_id,
+            &db_config.backend,
+            &statements,
+            execute_options(&req, &db_config, false)?,
         )
         .await
         .map_err(PostgateError::Executor)?;
@@ -77,6 +352,75 @@ pub async fn query_handler(
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Open a server-side cursor for a SELECT, to be paged through with /cursor/fetch
+pub async fn open_cursor_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<QueryRequest>,
+) -> Result<HttpResponse, PostgateError> {
+    let (token_info, db_config) = authenticate(&req, &state).await?;
+
+    let parsed = parse_and_validate_with_rules(&body.sql, &token_info.rules)?;
+
+    // Cursors are only declared for plain reads
+    if let Some(operation) = parsed
+        .operations
+        .iter()
+        .find(|op| **op != SqlOperation::Select)
+    {
+        return Err(ParseError::OperationNotAllowed(*operation).into());
+    }
+
+    let cursor_id = state
+        .executor_pool
+        .open_cursor(
+            token_info.database_id,
+            &db_config.backend,
+            &body,
+            execute_options(&req, &db_config, false)?,
+        )
+        .await
+        .map_err(PostgateError::Executor)?;
+
+    Ok(HttpResponse::Ok().json(OpenCursorResponse { cursor_id }))
+}
+
+pub async fn fetch_cursor_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<FetchCursorRequest>,
+) -> Result<HttpResponse, PostgateError> {
+    let (token_info, db_config) = authenticate(&req, &state).await?;
+
+    let page = state
+        .executor_pool
+        .fetch_cursor(
+            token_info.database_id,
+            body.cursor_id,
+            body.fetch,
+            execute_options(&req, &db_config, false)?,
+        )
+        .await
+        .map_err(PostgateError::Executor)?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn close_cursor_handler(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<CloseCursorRequest>,
+) -> Result<HttpResponse, PostgateError> {
+    let (token_info, _) = authenticate(&req, &state).await?;
+
+    state
+        .executor_pool
+        .close_cursor(token_info.database_id, body.cursor_id)
+        .map_err(PostgateError::Executor)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub async fn health_handler() -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({
         "status": "ok"
@@ -85,5 +429,10 @@ pub async fn health_handler() -> HttpResponse {
 
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.route("/health", web::get().to(health_handler))
-        .route("/query", web::post().to(query_handler));
+        .route("/query", web::post().to(query_handler))
+        .route("/transaction", web::post().to(transaction_handler))
+        .route("/cursor", web::post().to(open_cursor_handler))
+        .route("/cursor/fetch", web::post().to(fetch_cursor_handler))
+        .route("/cursor/close", web::post().to(close_cursor_handler))
+        .service(web::scope("/admin/v1").configure(crate::admin::configure_routes));
 }
@@ -1,10 +1,14 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgPool;
 use std::collections::HashSet;
 use thiserror::Error;
 use uuid::Uuid;
 
 use crate::auth::TokenInfo;
-use crate::config::{DatabaseBackend, DatabaseConfig, SqlOperation, TokenPermission};
+use crate::config::{
+    DatabaseBackend, DatabaseConfig, QueryLimits, QueryRules, SqlOperation, TokenPermission,
+};
 use crate::token::generate_token;
 
 #[derive(Debug, Error)]
@@ -20,6 +24,9 @@ pub enum StoreError {
 
     #[error("Token not found")]
     TokenNotFound,
+
+    #[error("Token expired")]
+    TokenExpired,
 }
 
 pub struct Store {
@@ -34,7 +41,10 @@ impl Store {
     pub async fn get_database(&self, id: Uuid) -> Result<DatabaseConfig, StoreError> {
         let row = sqlx::query!(
             r#"
-            SELECT id, name, backend_type, schema_name, connection_string, max_rows
+            SELECT id, name, backend_type, schema_name, connection_string, max_rows,
+                statement_timeout_ms, max_plan_cost, max_concurrent_queries, queue_timeout_ms,
+                max_connections, max_joins, max_subquery_depth, max_in_list, max_params,
+                max_sql_length
             FROM postgate_databases
             WHERE id = $1
             "#,
@@ -59,6 +69,18 @@ impl Store {
             name: row.name,
             backend,
             max_rows: row.max_rows,
+            statement_timeout_ms: row.statement_timeout_ms,
+            max_plan_cost: row.max_plan_cost,
+            max_concurrent_queries: row.max_concurrent_queries,
+            queue_timeout_ms: row.queue_timeout_ms,
+            max_connections: row.max_connections,
+            limits: QueryLimits {
+                max_joins: row.max_joins,
+                max_subquery_depth: row.max_subquery_depth,
+                max_in_list: row.max_in_list,
+                max_params: row.max_params,
+                max_sql_length: row.max_sql_length,
+            },
         })
     }
 
@@ -105,6 +127,12 @@ impl Store {
             name: name.to_string(),
             backend: backend.clone(),
             max_rows,
+            statement_timeout_ms: None,
+            max_plan_cost: None,
+            max_concurrent_queries: None,
+            queue_timeout_ms: None,
+            max_connections: None,
+            limits: QueryLimits::default(),
         })
     }
 
@@ -133,7 +161,10 @@ impl Store {
     pub async fn list_databases(&self) -> Result<Vec<DatabaseConfig>, StoreError> {
         let rows = sqlx::query!(
             r#"
-            SELECT id, name, backend_type, schema_name, connection_string, max_rows
+            SELECT id, name, backend_type, schema_name, connection_string, max_rows,
+                statement_timeout_ms, max_plan_cost, max_concurrent_queries, queue_timeout_ms,
+                max_connections, max_joins, max_subquery_depth, max_in_list, max_params,
+                max_sql_length
             FROM postgate_databases
             ORDER BY created_at DESC
             "#
@@ -158,6 +189,18 @@ impl Store {
                 name: row.name,
                 backend,
                 max_rows: row.max_rows,
+                statement_timeout_ms: row.statement_timeout_ms,
+                max_plan_cost: row.max_plan_cost,
+                max_concurrent_queries: row.max_concurrent_queries,
+                queue_timeout_ms: row.queue_timeout_ms,
+                max_connections: row.max_connections,
+                limits: QueryLimits {
+                    max_joins: row.max_joins,
+                    max_subquery_depth: row.max_subquery_depth,
+                    max_in_list: row.max_in_list,
+                    max_params: row.max_params,
+                    max_sql_length: row.max_sql_length,
+                },
             });
         }
 
@@ -166,28 +209,37 @@ impl Store {
 
     // ============ Token Methods ============
 
-    /// Create a new token for a database
+    /// Create a new token for a database, valid until `expires_at` if set
     /// Returns (token_id, full_token) - the full token is only returned once!
     pub async fn create_token(
         &self,
         database_id: Uuid,
         name: &str,
         permissions: &[TokenPermission],
+        expires_at: Option<DateTime<Utc>>,
+        restrictions: &TokenRestrictions,
     ) -> Result<(Uuid, String), StoreError> {
         let (full_token, token_hash, token_prefix) = generate_token();
         let ops_vec: Vec<String> = permissions.iter().map(|p| p.as_str().to_string()).collect();
 
         let token_id: Uuid = sqlx::query_scalar!(
             r#"
-            INSERT INTO postgate_tokens (database_id, name, token_hash, token_prefix, allowed_operations)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO postgate_tokens (database_id, name, token_hash, token_prefix, allowed_operations, expires_at,
+                allowed_tables, denied_tables, allowed_functions, denied_functions, safe_updates)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
             RETURNING id
             "#,
             database_id,
             name,
             token_hash,
             token_prefix,
-            &ops_vec
+            &ops_vec,
+            expires_at,
+            restrictions.allowed_tables.as_deref(),
+            &restrictions.denied_tables,
+            &restrictions.allowed_functions,
+            &restrictions.denied_functions,
+            restrictions.safe_updates
         )
         .fetch_one(&self.pool)
         .await?;
@@ -196,12 +248,16 @@ impl Store {
     }
 
     /// Validate a token by its hash and return the associated database_id and allowed_operations
+    /// The previous hash of a rotated token is accepted until its grace period ends.
+    /// Expired tokens are rejected with TokenExpired
     pub async fn validate_token(&self, token_hash: &str) -> Result<TokenInfo, StoreError> {
         let row = sqlx::query!(
             r#"
-            SELECT t.id, t.database_id, t.allowed_operations
+            SELECT t.id, t.database_id, t.allowed_operations, t.allowed_tables, t.denied_tables,
+                t.allowed_functions, t.denied_functions, t.safe_updates, t.expires_at
             FROM postgate_tokens t
             WHERE t.token_hash = $1
+               OR (t.previous_token_hash = $1 AND t.previous_token_expires_at > NOW())
             "#,
             token_hash
         )
@@ -209,6 +265,13 @@ impl Store {
         .await?
         .ok_or(StoreError::TokenNotFound)?;
 
+        if row
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(StoreError::TokenExpired);
+        }
+
         // Update last_used_at (fire and forget)
         let pool = self.pool.clone();
         let token_id = row.id;
@@ -233,6 +296,7 @@ impl Store {
                 "CREATE" => Some(SqlOperation::Create),
                 "ALTER" => Some(SqlOperation::Alter),
                 "DROP" => Some(SqlOperation::Drop),
+                "EXPLAIN" => Some(SqlOperation::Explain),
                 _ => None,
             })
             .collect();
@@ -240,7 +304,17 @@ impl Store {
         Ok(TokenInfo {
             database_id: row.database_id,
             token_id: row.id,
-            allowed_operations,
+            rules: QueryRules {
+                allowed_operations,
+                allowed_tables: row.allowed_tables.map(HashSet::from_iter),
+                denied_tables: HashSet::from_iter(row.denied_tables),
+                allowed_functions: HashSet::from_iter(row.allowed_functions),
+                denied_functions: HashSet::from_iter(row.denied_functions),
+                safe_updates: row.safe_updates,
+                // Set from the token's database when authenticating requests
+                limits: QueryLimits::default(),
+            },
+            expires_at: row.expires_at,
         })
     }
 
@@ -252,6 +326,27 @@ impl Store {
         Ok(())
     }
 
+    /// Delete a token of a database (TokenNotFound if it belongs to another one)
+    pub async fn delete_database_token(
+        &self,
+        database_id: Uuid,
+        token_id: Uuid,
+    ) -> Result<(), StoreError> {
+        let result = sqlx::query!(
+            "DELETE FROM postgate_tokens WHERE id = $1 AND database_id = $2",
+            token_id,
+            database_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoreError::TokenNotFound);
+        }
+
+        Ok(())
+    }
+
     /// Delete all tokens for a database
     pub async fn delete_tokens_for_database(&self, database_id: Uuid) -> Result<(), StoreError> {
         sqlx::query!(
@@ -267,7 +362,8 @@ impl Store {
     pub async fn list_tokens(&self, database_id: Uuid) -> Result<Vec<TokenListItem>, StoreError> {
         let rows = sqlx::query!(
             r#"
-            SELECT id, name, token_prefix, created_at, last_used_at
+            SELECT id, name, token_prefix, allowed_tables, denied_tables, allowed_functions,
+                denied_functions, safe_updates, created_at, last_used_at, expires_at
             FROM postgate_tokens
             WHERE database_id = $1
             ORDER BY created_at DESC
@@ -283,21 +379,50 @@ impl Store {
                 id: r.id,
                 name: r.name,
                 token_prefix: r.token_prefix,
+                restrictions: TokenRestrictions {
+                    allowed_tables: r.allowed_tables,
+                    denied_tables: r.denied_tables,
+                    allowed_functions: r.allowed_functions,
+                    denied_functions: r.denied_functions,
+                    safe_updates: r.safe_updates,
+                },
                 created_at: r.created_at,
                 last_used_at: r.last_used_at,
+                expires_at: r.expires_at,
             })
             .collect())
     }
 }
 
+/// Optional rules of a token, see `QueryRules`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenRestrictions {
+    /// Only these tables can be accessed (every table if None)
+    #[serde(default)]
+    pub allowed_tables: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_tables: Vec<String>,
+    /// Functions allowed even if denied by default
+    #[serde(default)]
+    pub allowed_functions: Vec<String>,
+    #[serde(default)]
+    pub denied_functions: Vec<String>,
+    /// Reject UPDATE and DELETE that don't filter rows
+    #[serde(default)]
+    pub safe_updates: bool,
+}
+
 /// Token info for listing (without the secret)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TokenListItem {
     pub id: Uuid,
     pub name: String,
     pub token_prefix: String,
-    pub created_at: chrono::DateTime<chrono::Utc>,
-    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(flatten)]
+    pub restrictions: TokenRestrictions,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 pub fn generate_schema_name(db_name: &str) -> String {
@@ -0,0 +1,416 @@
+//! Text forms of values without a dedicated decoder
+//!
+//! Result values arrive in Postgres' binary format. Types postgate has no JSON mapping
+//! for (geometric types, tsvector, bit strings, ranges, extension types like ltree or
+//! hstore...) are turned back into the text Postgres prints for them, so `SELECT *`
+//! works on any table. Types not covered here are a decode error for their column.
+
+use serde_json::Value as JsonValue;
+use sqlx::TypeInfo;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgTypeInfo, PgTypeKind};
+
+use crate::executor::numeric_binary_to_string;
+
+/// Text form of a binary value of the given type
+pub(crate) fn text_form(type_info: &PgTypeInfo, bytes: &[u8]) -> Result<String, BoxDynError> {
+    if let PgTypeKind::Range(element) = type_info.kind() {
+        return range(element, bytes);
+    }
+
+    let mut reader = Reader(bytes);
+    let name = type_info.name().to_ascii_lowercase();
+
+    let text = match name.as_str() {
+        // Binary and text forms are the same
+        "text" | "varchar" | "bpchar" | "name" | "unknown" | "citext" | "xml" => {
+            std::str::from_utf8(bytes)?.to_string()
+        }
+        // A version byte, then the text form
+        "ltree" | "lquery" | "ltxtquery" | "jsonpath" => match bytes.split_first() {
+            Some((1, text)) => std::str::from_utf8(text)?.to_string(),
+            _ => return Err(format!("unknown binary {} version", name).into()),
+        },
+        "int2" => reader.i16()?.to_string(),
+        "int4" => reader.i32()?.to_string(),
+        "int8" => reader.i64()?.to_string(),
+        "float4" => float(reader.f32()? as f64),
+        "float8" => float(reader.f64()?),
+        "numeric" => numeric_binary_to_string(bytes).ok_or("invalid binary NUMERIC")?,
+        "date" => date(reader.i32()?),
+        "timestamp" => timestamp(reader.i64()?, |v| v.to_string()),
+        "timestamptz" => timestamp(reader.i64()?, |v| v.and_utc().to_rfc3339()),
+        "point" => point(&mut reader)?,
+        "lseg" => format!("[{},{}]", point(&mut reader)?, point(&mut reader)?),
+        "box" => format!("{},{}", point(&mut reader)?, point(&mut reader)?),
+        "line" => format!(
+            "{{{},{},{}}}",
+            float(reader.f64()?),
+            float(reader.f64()?),
+            float(reader.f64()?)
+        ),
+        "circle" => format!("<{},{}>", point(&mut reader)?, float(reader.f64()?)),
+        "path" => {
+            let closed = reader.u8()? != 0;
+            let points = points(&mut reader)?;
+            match closed {
+                true => format!("({})", points),
+                false => format!("[{}]", points),
+            }
+        }
+        "polygon" => format!("({})", points(&mut reader)?),
+        "bit" | "varbit" => bits(&mut reader)?,
+        "pg_lsn" => {
+            let lsn = reader.u64()?;
+            format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF)
+        }
+        "tsvector" => tsvector(&mut reader)?,
+        "hstore" => hstore(&mut reader)?,
+        _ => return Err(format!("no text form known for binary {} values", name).into()),
+    };
+
+    Ok(text)
+}
+
+/// Elements of a binary array as JSON strings, nested for multidimensional arrays
+pub(crate) fn array_text_forms(
+    element: &PgTypeInfo,
+    bytes: &[u8],
+) -> Result<JsonValue, BoxDynError> {
+    let mut reader = Reader(bytes);
+
+    let dimensions = reader.i32()?;
+    let _has_nulls = reader.i32()?;
+    let _element_oid = reader.u32()?;
+
+    let mut lengths = Vec::new();
+    for _ in 0..dimensions {
+        lengths.push(usize::try_from(reader.i32()?)?);
+        let _lower_bound = reader.i32()?;
+    }
+
+    if lengths.is_empty() {
+        return Ok(JsonValue::Array(vec![]));
+    }
+
+    let mut values = Vec::new();
+    for _ in 0..lengths.iter().product::<usize>() {
+        values.push(match reader.value()? {
+            Some(bytes) => JsonValue::String(text_form(element, bytes)?),
+            None => JsonValue::Null,
+        });
+    }
+
+    // Group the innermost dimension first
+    for &length in lengths[1..].iter().rev() {
+        values = values
+            .chunks(length)
+            .map(|chunk| JsonValue::Array(chunk.to_vec()))
+            .collect();
+    }
+
+    Ok(JsonValue::Array(values))
+}
+
+/// `[1,10)`, `(,5]` or `empty`
+fn range(element: &PgTypeInfo, bytes: &[u8]) -> Result<String, BoxDynError> {
+    const EMPTY: u8 = 0x01;
+    const LOWER_INCLUSIVE: u8 = 0x02;
+    const UPPER_INCLUSIVE: u8 = 0x04;
+    const LOWER_INFINITE: u8 = 0x08;
+    const UPPER_INFINITE: u8 = 0x10;
+
+    let mut reader = Reader(bytes);
+    let flags = reader.u8()?;
+    if flags & EMPTY != 0 {
+        return Ok("empty".to_string());
+    }
+
+    let mut bound = |infinite: u8| -> Result<String, BoxDynError> {
+        if flags & infinite != 0 {
+            return Ok(String::new());
+        }
+        let bytes = reader.value()?.ok_or("NULL range bound")?;
+        Ok(quote_range_bound(text_form(element, bytes)?))
+    };
+    let lower = bound(LOWER_INFINITE)?;
+    let upper = bound(UPPER_INFINITE)?;
+
+    Ok(format!(
+        "{}{},{}{}",
+        if flags & LOWER_INCLUSIVE != 0 {
+            '['
+        } else {
+            '('
+        },
+        lower,
+        upper,
+        if flags & UPPER_INCLUSIVE != 0 {
+            ']'
+        } else {
+            ')'
+        },
+    ))
+}
+
+/// Bounds with delimiters or spaces are double-quoted, like Postgres does
+fn quote_range_bound(bound: String) -> String {
+    let special = |c: char| "\"\\()[],".contains(c) || c.is_whitespace();
+    if !bound.is_empty() && !bound.contains(special) {
+        return bound;
+    }
+
+    let escaped = bound.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+/// Shortest round-trip form, with an exponent for very large or small values
+/// (`1e+20`, `1.5e-07`), as Postgres prints floats
+fn float(value: f64) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').expect("exponent");
+    let exponent: i32 = exponent.parse().expect("exponent");
+
+    if value != 0.0 && !(-4..15).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+    } else {
+        value.to_string()
+    }
+}
+
+fn point(reader: &mut Reader) -> Result<String, BoxDynError> {
+    Ok(format!(
+        "({},{})",
+        float(reader.f64()?),
+        float(reader.f64()?)
+    ))
+}
+
+/// Comma-separated points, preceded by their count
+fn points(reader: &mut Reader) -> Result<String, BoxDynError> {
+    let count = reader.i32()?;
+    let points = (0..count)
+        .map(|_| point(reader))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(points.join(","))
+}
+
+/// Days since 2000-01-01
+fn date(days: i32) -> String {
+    match days {
+        i32::MAX => "infinity".to_string(),
+        i32::MIN => "-infinity".to_string(),
+        days => {
+            let epoch = chrono::NaiveDate::from_ymd_opt(2000, 1, 1).expect("valid date");
+            epoch
+                .checked_add_signed(chrono::TimeDelta::days(days as i64))
+                .map_or_else(|| days.to_string(), |date| date.to_string())
+        }
+    }
+}
+
+/// Microseconds since 2000-01-01 00:00:00
+fn timestamp(micros: i64, format: impl Fn(chrono::NaiveDateTime) -> String) -> String {
+    match micros {
+        i64::MAX => "infinity".to_string(),
+        i64::MIN => "-infinity".to_string(),
+        micros => {
+            let epoch = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .expect("valid timestamp");
+            epoch
+                .checked_add_signed(chrono::TimeDelta::microseconds(micros))
+                .map_or_else(|| micros.to_string(), format)
+        }
+    }
+}
+
+/// Bit count, then the bits packed from the most significant one
+fn bits(reader: &mut Reader) -> Result<String, BoxDynError> {
+    let count = usize::try_from(reader.i32()?)?;
+    let bytes = reader.take(count.div_ceil(8))?;
+
+    Ok((0..count)
+        .map(|i| match bytes[i / 8] & (0x80 >> (i % 8)) {
+            0 => '0',
+            _ => '1',
+        })
+        .collect())
+}
+
+/// `'cat':1A,3 'sat':2`. Each position holds its weight in the top two bits.
+fn tsvector(reader: &mut Reader) -> Result<String, BoxDynError> {
+    let count = reader.i32()?;
+    let mut lexemes = Vec::new();
+
+    for _ in 0..count {
+        let lexeme = reader.cstring()?;
+        let mut text = format!("'{}'", lexeme.replace('\'', "''").replace('\\', "\\\\"));
+
+        let positions = (0..reader.u16()?)
+            .map(|_| {
+                let position = reader.u16()?;
+                let weight = match position >> 14 {
+                    3 => "A",
+                    2 => "B",
+                    1 => "C",
+                    _ => "",
+                };
+                Ok(format!("{}{}", position & 0x3FFF, weight))
+            })
+            .collect::<Result<Vec<_>, BoxDynError>>()?;
+        if !positions.is_empty() {
+            text.push(':');
+            text.push_str(&positions.join(","));
+        }
+
+        lexemes.push(text);
+    }
+
+    Ok(lexemes.join(" "))
+}
+
+/// `"a"=>"1", "b"=>NULL`
+fn hstore(reader: &mut Reader) -> Result<String, BoxDynError> {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let count = reader.i32()?;
+    let mut pairs = Vec::new();
+    for _ in 0..count {
+        let key = reader.value()?.ok_or("NULL hstore key")?;
+        let key = quote(std::str::from_utf8(key)?);
+        let value = match reader.value()? {
+            Some(value) => quote(std::str::from_utf8(value)?),
+            None => "NULL".to_string(),
+        };
+        pairs.push(format!("{}=>{}", key, value));
+    }
+
+    Ok(pairs.join(", "))
+}
+
+/// Big-endian reads over a binary value
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BoxDynError> {
+        if self.0.len() < len {
+            return Err("binary value is too short".into());
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BoxDynError> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8, BoxDynError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BoxDynError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn i16(&mut self) -> Result<i16, BoxDynError> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, BoxDynError> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, BoxDynError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, BoxDynError> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, BoxDynError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, BoxDynError> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, BoxDynError> {
+        Ok(f64::from_be_bytes(self.array()?))
+    }
+
+    /// NUL-terminated UTF-8 string
+    fn cstring(&mut self) -> Result<&'a str, BoxDynError> {
+        let len = self
+            .0
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("unterminated string")?;
+        let text = std::str::from_utf8(self.take(len)?)?;
+        self.take(1)?;
+        Ok(text)
+    }
+
+    /// Length-prefixed value, None for NULL (length -1)
+    fn value(&mut self) -> Result<Option<&'a [u8]>, BoxDynError> {
+        match self.i32()? {
+            -1 => Ok(None),
+            len => Ok(Some(self.take(usize::try_from(len)?)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floats_like_postgres() {
+        assert_eq!(float(1.0), "1");
+        assert_eq!(float(-2.5), "-2.5");
+        assert_eq!(float(0.0001), "0.0001");
+        assert_eq!(float(0.00001), "1e-05");
+        assert_eq!(float(1e20), "1e+20");
+        assert_eq!(float(123456789012345.0), "123456789012345");
+        assert_eq!(float(f64::NAN), "NaN");
+    }
+
+    #[test]
+    fn range_bounds_quoted_when_needed() {
+        assert_eq!(quote_range_bound("12".to_string()), "12");
+        assert_eq!(
+            quote_range_bound("2024-01-01 10:00:00".to_string()),
+            "\"2024-01-01 10:00:00\""
+        );
+        assert_eq!(quote_range_bound("a\"b".to_string()), "\"a\\\"b\"");
+    }
+
+    #[test]
+    fn dates_and_timestamps_from_the_2000_epoch() {
+        assert_eq!(date(0), "2000-01-01");
+        assert_eq!(date(-1), "1999-12-31");
+        assert_eq!(date(i32::MAX), "infinity");
+        assert_eq!(
+            timestamp(1_500_000, |v| v.to_string()),
+            "2000-01-01 00:00:01.500"
+        );
+    }
+
+    #[test]
+    fn bit_strings() {
+        let bytes = [0, 0, 0, 10, 0b1010_0000, 0b0100_0000];
+        assert_eq!(bits(&mut Reader(&bytes)).unwrap(), "1010000001");
+    }
+}
@@ -3,6 +3,7 @@
 //! Tokens are formatted as: pg_<random_32_bytes_hex>
 //! The hash is SHA-256 of the full token string
 
+use chrono::{DateTime, Duration, Utc};
 use sha2::{Digest, Sha256};
 
 /// Token prefix for identification
@@ -33,6 +34,38 @@ pub fn is_valid_format(token: &str) -> bool {
     token.starts_with(TOKEN_PREFIX) && token.len() == TOKEN_PREFIX.len() + 64
 }
 
+/// Parse a duration such as "30m", "12h" or "7d" (token TTL, rotation grace period)
+/// Units: s (seconds), m (minutes), h (hours), d (days)
+pub fn parse_ttl(ttl: &str) -> Result<Duration, String> {
+    let ttl = ttl.trim();
+    let invalid = || format!("Invalid duration: {}. Expected e.g. 30m, 12h or 7d", ttl);
+
+    let (split, _) = ttl.char_indices().last().ok_or_else(invalid)?;
+    let (value, unit) = ttl.split_at(split);
+    let value: i64 = value.parse().map_err(|_| invalid())?;
+
+    if value <= 0 {
+        return Err(invalid());
+    }
+
+    let duration = match unit {
+        "s" => Duration::try_seconds(value),
+        "m" => Duration::try_minutes(value),
+        "h" => Duration::try_hours(value),
+        "d" => Duration::try_days(value),
+        _ => None,
+    };
+
+    duration.ok_or_else(invalid)
+}
+
+/// Point in time `duration` from now, an error past the last representable date
+pub fn from_now(duration: Duration) -> Result<DateTime<Utc>, String> {
+    Utc::now()
+        .checked_add_signed(duration)
+        .ok_or_else(|| "Duration is too long".to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,4 +118,29 @@ mod tests {
             "pg_0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef00"
         ));
     }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("45s").unwrap(), Duration::seconds(45));
+        assert_eq!(parse_ttl("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_ttl("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse_ttl("7d").unwrap(), Duration::days(7));
+
+        assert!(parse_ttl("").is_err());
+        assert!(parse_ttl("d").is_err());
+        assert!(parse_ttl("7").is_err());
+        assert!(parse_ttl("7w").is_err());
+        assert!(parse_ttl("7é").is_err());
+        assert!(parse_ttl("0d").is_err());
+        assert!(parse_ttl("-1h").is_err());
+        assert!(parse_ttl("99999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_from_now() {
+        assert!(from_now(Duration::days(7)).unwrap() > Utc::now());
+
+        // A valid duration can still end past the last representable date
+        assert!(from_now(parse_ttl("100000000000d").unwrap()).is_err());
+    }
 }